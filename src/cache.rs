@@ -1,69 +1,527 @@
 use anyhow::{Context, Result};
+use chrono::Utc;
+use log::{error, info, warn};
+use serde::{Deserialize, Serialize};
 use std::{
-    collections::HashMap,
-    fs,
+    collections::{BTreeMap, HashMap},
+    fmt, fs,
     path::{Path, PathBuf},
-    sync::{Arc, RwLock},
+    str::FromStr,
+    sync::{
+        Arc, Mutex, RwLock,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::Duration,
 };
+use tokio::{
+    sync::{Notify, watch},
+    time::sleep,
+};
+
+use crate::{
+    config::ItemCacheConfig,
+    files::write_atomic,
+    models::{Asset, AssetClassInfo},
+    ratelimit::RateLimiter,
+    steam::{SteamClient, find_class_info},
+};
+
+/// Version of the `cache.json` layout written by this build.
+const CACHE_VERSION: u32 = 3;
+/// How long after a change the file is written, so that a burst of lookups is saved at once.
+const FLUSH_DELAY: Duration = Duration::from_secs(5);
+/// How often stale descriptions are looked for.
+const REFRESH_INTERVAL: Duration = Duration::from_secs(10 * 60);
+/// Most descriptions refreshed per round, to go easy on the API.
+const REFRESH_BATCH: usize = 100;
+/// Eviction makes room for a tenth of `max_entries` at once.
+const EVICT_BATCH_DIVISOR: usize = 10;
+
+/// Identifies an item description. Class IDs are only unique within an app, and the instance
+/// distinguishes copies of a class with different details, such as stickers or descriptions.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ItemKey {
+    pub appid: u32,
+    pub classid: String,
+    pub instanceid: String,
+}
+
+impl ItemKey {
+    pub fn new(appid: u32, classid: &str, instanceid: &str) -> Self {
+        Self {
+            appid,
+            classid: classid.to_string(),
+            instanceid: instanceid.to_string(),
+        }
+    }
+
+    pub fn of(asset: &Asset) -> Self {
+        Self::new(asset.appid, &asset.classid, &asset.instanceid)
+    }
+}
+
+/// Written as `appid_classid_instanceid`, the form used for keys in `cache.json`.
+impl fmt::Display for ItemKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}_{}_{}", self.appid, self.classid, self.instanceid)
+    }
+}
 
-use crate::models::AssetClassInfo;
+impl FromStr for ItemKey {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut parts = s.splitn(3, '_');
+        match (parts.next(), parts.next(), parts.next()) {
+            (Some(appid), Some(classid), Some(instanceid))
+                if !classid.is_empty() && !instanceid.is_empty() =>
+            {
+                let appid = appid
+                    .parse()
+                    .with_context(|| format!("Invalid appid in item key {:?}", s))?;
+                Ok(Self::new(appid, classid, instanceid))
+            }
+            _ => anyhow::bail!(
+                "Expected an item key like 440_101785959_11040578, got {:?}",
+                s
+            ),
+        }
+    }
+}
+
+/// A cached description, with when it was fetched and last used as Unix times.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct CachedItem {
+    #[serde(flatten)]
+    pub info: AssetClassInfo,
+    pub fetched_at: u64,
+    pub last_used: u64,
+}
+
+/// The layout of `cache.json`.
+#[derive(Debug, Deserialize, Serialize)]
+struct CacheFile<T> {
+    version: u32,
+    items: BTreeMap<String, T>,
+    /// Entries from version 1 files, keyed by class ID alone, that haven't been looked up yet.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    legacy: BTreeMap<String, AssetClassInfo>,
+}
+
+/// The items read from a cache file.
+#[derive(Default)]
+struct LoadedItems {
+    items: HashMap<ItemKey, CachedItem>,
+    legacy: HashMap<String, AssetClassInfo>,
+    /// The file isn't in the current layout, and should be rewritten.
+    outdated: bool,
+}
 
 #[derive(Clone)]
 pub struct ItemCache {
-    data: Arc<RwLock<HashMap<String, AssetClassInfo>>>,
+    data: Arc<RwLock<HashMap<ItemKey, CachedItem>>>,
+    /// Version 1 entries by class ID, moved into `data` under their full key on first lookup.
+    legacy: Arc<RwLock<HashMap<String, AssetClassInfo>>>,
     file_path: PathBuf,
+    /// Descriptions older than this many seconds are refreshed; 0 never refreshes.
+    ttl_seconds: u64,
+    /// The least recently used descriptions are dropped beyond this many.
+    max_entries: usize,
+    /// Set while `data` has changes that aren't on disk yet.
+    dirty: Arc<AtomicBool>,
+    changed: Arc<Notify>,
+    /// Held while writing the file, so two writes can't interleave.
+    write_lock: Arc<Mutex<()>>,
 }
 
 impl ItemCache {
-    pub fn new<P: AsRef<Path>>(path: P) -> Result<Self> {
+    /// Loads the cache file, upgrading files written by older versions.
+    pub fn new<P: AsRef<Path>>(path: P, config: &ItemCacheConfig) -> Result<Self> {
         let file_path = path.as_ref().to_path_buf();
-        let mut data = HashMap::new();
+        let mut loaded = LoadedItems::default();
 
         if file_path.exists() {
             let content = fs::read_to_string(&file_path)
                 .with_context(|| format!("Failed to read cache file: {:?}", file_path))?;
             if !content.is_empty() {
-                data = serde_json::from_str(&content)
+                let value: serde_json::Value = serde_json::from_str(&content)
                     .with_context(|| format!("Failed to parse cache file: {:?}", file_path))?;
+                loaded = load_items(value)
+                    .with_context(|| format!("Failed to load cache file: {:?}", file_path))?;
             }
         }
 
-        Ok(Self {
-            data: Arc::new(RwLock::new(data)),
+        let cache = Self {
+            data: Arc::new(RwLock::new(loaded.items)),
+            legacy: Arc::new(RwLock::new(loaded.legacy)),
             file_path,
-        })
+            ttl_seconds: config.ttl_seconds,
+            max_entries: config.max_entries,
+            dirty: Arc::new(AtomicBool::new(false)),
+            changed: Arc::new(Notify::new()),
+            write_lock: Arc::new(Mutex::new(())),
+        };
+        let evicted = cache.evict()?;
+        if loaded.outdated || evicted > 0 {
+            cache.save()?;
+        }
+        Ok(cache)
     }
 
-    pub fn get(&self, classid: &str, _instanceid: &str) -> Option<AssetClassInfo> {
-        let key = classid.to_string();
-        let data = self.data.read().ok()?;
-        data.get(&key).cloned()
-    }
+    /// The item's description, stale or not. Marks it as recently used.
+    pub fn get(&self, key: &ItemKey) -> Option<AssetClassInfo> {
+        let mut data = self.data.write().ok()?;
+        if let Some(entry) = data.get_mut(key) {
+            entry.last_used = now();
+            return Some(entry.info.clone());
+        }
 
-    pub fn insert(&self, classid: &str, _instanceid: &str, info: AssetClassInfo) -> Result<()> {
-        let key = classid.to_string();
+        // A version 1 entry is moved under the full key on first use. Class IDs can repeat
+        // across apps, so it is marked stale for the refresher to fetch this exact item again.
+        let info = self.legacy.write().ok()?.remove(&key.classid)?;
+        data.insert(
+            key.clone(),
+            CachedItem {
+                info: info.clone(),
+                fetched_at: 0,
+                last_used: now(),
+            },
+        );
+        drop(data);
+        self.mark_changed();
+        Some(info)
+    }
 
+    /// Adds or replaces an item, dropping the least recently used items if the cache is full.
+    /// It is written to disk by `run_flusher`, or the next `flush`.
+    pub fn insert(&self, key: ItemKey, info: AssetClassInfo) -> Result<()> {
         {
             let mut data = self
                 .data
                 .write()
                 .map_err(|_| anyhow::anyhow!("Lock poisoned"))?;
-            data.insert(key, info);
+            let now = now();
+            data.insert(
+                key,
+                CachedItem {
+                    info,
+                    fetched_at: now,
+                    last_used: now,
+                },
+            );
         }
+        self.evict()?;
 
-        self.save()?;
+        self.mark_changed();
         Ok(())
     }
 
-    fn save(&self) -> Result<()> {
+    /// Once there are more than `max_entries` items, drops the least recently used down to
+    /// 90% of it, so that inserts at the cap don't each have to rank every entry. Returns how
+    /// many were dropped.
+    fn evict(&self) -> Result<usize> {
+        let mut data = self
+            .data
+            .write()
+            .map_err(|_| anyhow::anyhow!("Lock poisoned"))?;
+        if data.len() <= self.max_entries {
+            return Ok(0);
+        }
+        let excess = data.len() - (self.max_entries - self.max_entries / EVICT_BATCH_DIVISOR);
+
+        let mut by_use: Vec<(u64, ItemKey)> = data
+            .iter()
+            .map(|(key, entry)| (entry.last_used, key.clone()))
+            .collect();
+        by_use.select_nth_unstable(excess - 1);
+        for (_, key) in by_use.into_iter().take(excess) {
+            data.remove(&key);
+        }
+        Ok(excess)
+    }
+
+    /// Up to `limit` items older than the TTL, oldest first.
+    fn stale(&self, limit: usize) -> Vec<ItemKey> {
+        if self.ttl_seconds == 0 {
+            return Vec::new();
+        }
+        let Ok(data) = self.data.read() else {
+            return Vec::new();
+        };
+        let cutoff = now().saturating_sub(self.ttl_seconds);
+        let mut stale: Vec<(u64, ItemKey)> = data
+            .iter()
+            .filter(|(_, entry)| entry.fetched_at <= cutoff)
+            .map(|(key, entry)| (entry.fetched_at, key.clone()))
+            .collect();
+        stale.sort();
+        stale.into_iter().take(limit).map(|(_, key)| key).collect()
+    }
+
+    /// Records a fresh fetch of an item, replacing its description when Steam returned one.
+    fn refreshed(&self, key: &ItemKey, info: Option<AssetClassInfo>) {
+        if let Ok(mut data) = self.data.write()
+            && let Some(entry) = data.get_mut(key)
+        {
+            if let Some(info) = info {
+                entry.info = info;
+            }
+            entry.fetched_at = now();
+        }
+        self.mark_changed();
+    }
+
+    /// Every so often, fetches stale descriptions again from Steam, so renamed items pick up
+    /// their new names. Each round uses the API key `api_key` holds at the time, and is skipped
+    /// when it holds none. Runs forever, unless the TTL is 0.
+    pub async fn run_refresher(
+        self: Arc<Self>,
+        api_key: watch::Receiver<Option<String>>,
+        limiter: RateLimiter,
+    ) {
+        if self.ttl_seconds == 0 {
+            return;
+        }
+
+        loop {
+            sleep(REFRESH_INTERVAL).await;
+
+            let Some(api_key) = api_key.borrow().clone() else {
+                continue;
+            };
+            let stale = self.stale(REFRESH_BATCH);
+            if stale.is_empty() {
+                continue;
+            }
+            let client = SteamClient::new(api_key, limiter.clone());
+
+            let mut by_appid: HashMap<u32, Vec<ItemKey>> = HashMap::new();
+            for key in &stale {
+                by_appid.entry(key.appid).or_default().push(key.clone());
+            }
+
+            let mut refreshed = 0;
+            for (appid, keys) in by_appid {
+                let pairs: Vec<(String, String)> = keys
+                    .iter()
+                    .map(|key| (key.classid.clone(), key.instanceid.clone()))
+                    .collect();
+                match client.get_asset_class_info(appid, &pairs).await {
+                    Ok(infos) => {
+                        for key in keys {
+                            // Items Steam no longer describes keep their old description
+                            // until they are stale again.
+                            let info = find_class_info(&infos, &key.classid, &key.instanceid);
+                            refreshed += usize::from(info.is_some());
+                            self.refreshed(&key, info.cloned());
+                        }
+                    }
+                    Err(e) => warn!(
+                        "Failed to refresh item descriptions for app {}: {:#}",
+                        appid, e
+                    ),
+                }
+            }
+            info!(
+                "Refreshed {} of {} stale item descriptions.",
+                refreshed,
+                stale.len()
+            );
+        }
+    }
+
+    fn mark_changed(&self) {
+        self.dirty.store(true, Ordering::SeqCst);
+        self.changed.notify_one();
+    }
+
+    /// Every cached item, ordered by key.
+    pub fn entries(&self) -> Vec<(ItemKey, CachedItem)> {
+        let Ok(data) = self.data.read() else {
+            return Vec::new();
+        };
+        let mut entries: Vec<_> = data
+            .iter()
+            .map(|(key, entry)| (key.clone(), entry.clone()))
+            .collect();
+        entries.sort_by(|a, b| a.0.cmp(&b.0));
+        entries
+    }
+
+    /// Removes every item `matches` accepts, returning how many.
+    pub fn purge<F>(&self, matches: F) -> Result<usize>
+    where
+        F: Fn(&ItemKey, &CachedItem) -> bool,
+    {
+        let removed = {
+            let mut data = self
+                .data
+                .write()
+                .map_err(|_| anyhow::anyhow!("Lock poisoned"))?;
+            let before = data.len();
+            data.retain(|key, entry| !matches(key, entry));
+            before - data.len()
+        };
+
+        if removed > 0 {
+            self.mark_changed();
+        }
+        Ok(removed)
+    }
+
+    /// Writes every item to `path`, in the same layout as the cache file.
+    pub fn export(&self, path: &Path) -> Result<usize> {
+        fs::write(path, self.to_json()?)
+            .with_context(|| format!("Failed to write export file: {:?}", path))?;
+        Ok(self.len())
+    }
+
+    /// Adds the items from an exported or cache file of any version, replacing items already
+    /// cached. Returns how many were read.
+    pub fn import(&self, path: &Path) -> Result<usize> {
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read import file: {:?}", path))?;
+        let value: serde_json::Value = serde_json::from_str(&content)
+            .with_context(|| format!("Failed to parse import file: {:?}", path))?;
+        let loaded =
+            load_items(value).with_context(|| format!("Failed to load import file: {:?}", path))?;
+
+        let count = loaded.items.len() + loaded.legacy.len();
+        {
+            let mut data = self
+                .data
+                .write()
+                .map_err(|_| anyhow::anyhow!("Lock poisoned"))?;
+            data.extend(loaded.items);
+            let mut legacy = self
+                .legacy
+                .write()
+                .map_err(|_| anyhow::anyhow!("Lock poisoned"))?;
+            legacy.extend(loaded.legacy);
+        }
+        self.evict()?;
+
+        self.mark_changed();
+        Ok(count)
+    }
+
+    pub fn len(&self) -> usize {
+        self.data.read().map_or(0, |data| data.len())
+    }
+
+    /// Writes the cache to disk a few seconds after it changes, together with any other
+    /// changes made in the meantime. Runs forever.
+    pub async fn run_flusher(self: Arc<Self>) {
+        loop {
+            self.changed.notified().await;
+            sleep(FLUSH_DELAY).await;
+            if let Err(e) = self.flush() {
+                error!("Failed to save item cache: {:#}", e);
+            }
+        }
+    }
+
+    /// Writes pending changes to disk now, if there are any.
+    pub fn flush(&self) -> Result<()> {
+        if !self.dirty.swap(false, Ordering::SeqCst) {
+            return Ok(());
+        }
+        self.save()
+            .inspect_err(|_| self.dirty.store(true, Ordering::SeqCst))
+    }
+
+    fn to_json(&self) -> Result<String> {
         let data = self
             .data
             .read()
             .map_err(|_| anyhow::anyhow!("Lock poisoned"))?;
-        let content = serde_json::to_string_pretty(&*data)?;
-        fs::write(&self.file_path, content)
-            .with_context(|| format!("Failed to write cache file: {:?}", self.file_path))?;
-        Ok(())
+        let legacy = self
+            .legacy
+            .read()
+            .map_err(|_| anyhow::anyhow!("Lock poisoned"))?;
+        let file = CacheFile {
+            version: CACHE_VERSION,
+            items: data
+                .iter()
+                .map(|(key, entry)| (key.to_string(), entry))
+                .collect(),
+            legacy: legacy
+                .iter()
+                .map(|(classid, info)| (classid.clone(), info.clone()))
+                .collect(),
+        };
+        Ok(serde_json::to_string_pretty(&file)?)
+    }
+
+    fn save(&self) -> Result<()> {
+        let _writing = self
+            .write_lock
+            .lock()
+            .map_err(|_| anyhow::anyhow!("Lock poisoned"))?;
+
+        write_atomic(&self.file_path, &self.to_json()?, "cache")
+    }
+}
+
+fn now() -> u64 {
+    Utc::now().timestamp() as u64
+}
+
+/// Reads the items from a parsed cache file of any version.
+fn load_items(value: serde_json::Value) -> Result<LoadedItems> {
+    let version = value.get("version").and_then(|v| v.as_u64());
+    match version {
+        // Version 1 was a bare map keyed by class ID alone. The app and instance aren't known
+        // until an item is looked up, so the entries are kept aside and re-keyed then.
+        None => {
+            let legacy: HashMap<String, AssetClassInfo> = serde_json::from_value(value)?;
+            info!(
+                "Upgrading item cache keyed by class ID only; {} entries are re-keyed as they are used.",
+                legacy.len()
+            );
+            Ok(LoadedItems {
+                legacy,
+                outdated: true,
+                ..Default::default()
+            })
+        }
+        // Version 2 had no timestamps. Its entries are treated as stale, so they are
+        // refreshed in the background.
+        Some(2) => {
+            let file: CacheFile<AssetClassInfo> = serde_json::from_value(value)?;
+            let now = now();
+            let mut items = HashMap::new();
+            for (key, info) in file.items {
+                let entry = CachedItem {
+                    info,
+                    fetched_at: 0,
+                    last_used: now,
+                };
+                items.insert(key.parse()?, entry);
+            }
+            Ok(LoadedItems {
+                items,
+                legacy: file.legacy.into_iter().collect(),
+                outdated: true,
+            })
+        }
+        Some(v) if v == u64::from(CACHE_VERSION) => {
+            let file: CacheFile<CachedItem> = serde_json::from_value(value)?;
+            let mut items = HashMap::new();
+            for (key, entry) in file.items {
+                items.insert(key.parse()?, entry);
+            }
+            Ok(LoadedItems {
+                items,
+                legacy: file.legacy.into_iter().collect(),
+                outdated: false,
+            })
+        }
+        Some(v) => anyhow::bail!(
+            "Unsupported cache version {}; this build reads version {}",
+            v,
+            CACHE_VERSION
+        ),
     }
 }
 
@@ -104,25 +562,33 @@ mod tests {
 
         // Scope to ensure cache saves and variables are dropped before strict file check if needed (though save is explicit)
         {
-            let cache = ItemCache::new(&path).expect("Failed to create cache");
+            let cache =
+                ItemCache::new(&path, &ItemCacheConfig::default()).expect("Failed to create cache");
 
             let info = create_dummy_info();
 
             // Test Insert
             cache
-                .insert("100", "0", info.clone())
+                .insert(ItemKey::new(440, "100", "0"), info.clone())
                 .expect("Failed to insert");
 
             // Test Get
-            let retrieved = cache.get("100", "0");
+            let retrieved = cache.get(&ItemKey::new(440, "100", "0"));
             assert!(retrieved.is_some());
             assert_eq!(retrieved.unwrap().name, "Test Item");
+
+            // Inserts are only written on flush
+            assert!(!path.exists());
+            cache.flush().expect("Failed to flush");
+            assert!(path.exists());
+            assert!(!path.with_extension("json.tmp").exists());
         } // cache dropped
 
         // Test Persistence (Load from disk)
         {
-            let cache2 = ItemCache::new(&path).expect("Failed to load cache");
-            let retrieved2 = cache2.get("100", "0");
+            let cache2 =
+                ItemCache::new(&path, &ItemCacheConfig::default()).expect("Failed to load cache");
+            let retrieved2 = cache2.get(&ItemKey::new(440, "100", "0"));
             assert!(retrieved2.is_some());
             assert_eq!(retrieved2.unwrap().name, "Test Item");
         }
@@ -130,4 +596,156 @@ mod tests {
         // Cleanup
         let _ = fs::remove_file(&path);
     }
+
+    #[test]
+    fn test_keys_include_app_and_instance() {
+        let path = get_temp_file_path();
+        let cache =
+            ItemCache::new(&path, &ItemCacheConfig::default()).expect("Failed to create cache");
+
+        let mut other = create_dummy_info();
+        other.name = "Other Item".to_string();
+        cache
+            .insert(ItemKey::new(440, "100", "0"), create_dummy_info())
+            .unwrap();
+        cache
+            .insert(ItemKey::new(730, "100", "0"), other.clone())
+            .unwrap();
+        cache.insert(ItemKey::new(440, "100", "5"), other).unwrap();
+
+        assert_eq!(
+            cache.get(&ItemKey::new(440, "100", "0")).unwrap().name,
+            "Test Item"
+        );
+        assert_eq!(
+            cache.get(&ItemKey::new(730, "100", "0")).unwrap().name,
+            "Other Item"
+        );
+        assert_eq!(
+            cache.get(&ItemKey::new(440, "100", "5")).unwrap().name,
+            "Other Item"
+        );
+
+        let key: ItemKey = "440_100_5".parse().unwrap();
+        assert_eq!(key, ItemKey::new(440, "100", "5"));
+        assert_eq!(key.to_string(), "440_100_5");
+        assert!("100".parse::<ItemKey>().is_err());
+
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_migrates_unversioned_file() {
+        let path = get_temp_file_path();
+        let old = HashMap::from([
+            ("100".to_string(), create_dummy_info()),
+            ("200".to_string(), create_dummy_info()),
+        ]);
+        fs::write(&path, serde_json::to_string(&old).unwrap()).unwrap();
+
+        let cache =
+            ItemCache::new(&path, &ItemCacheConfig::default()).expect("Failed to migrate cache");
+        let file: CacheFile<CachedItem> =
+            serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(file.version, CACHE_VERSION);
+        assert!(file.items.is_empty());
+        assert_eq!(file.legacy.len(), 2);
+
+        // The first lookup re-keys the entry, as stale so it is fetched again.
+        let key = ItemKey::new(440, "100", "11040578");
+        assert!(cache.get(&key).is_some());
+        assert_eq!(cache.stale(10), vec![key.clone()]);
+        cache.flush().unwrap();
+
+        let cache = ItemCache::new(&path, &ItemCacheConfig::default()).unwrap();
+        assert!(cache.get(&key).is_some());
+        assert!(cache.get(&ItemKey::new(730, "200", "0")).is_some());
+        assert!(cache.get(&ItemKey::new(730, "200", "1")).is_none());
+
+        fs::write(&path, r#"{ "version": 99, "items": {} }"#).unwrap();
+        assert!(ItemCache::new(&path, &ItemCacheConfig::default()).is_err());
+
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_evicts_least_recently_used() {
+        let path = get_temp_file_path();
+        let config = ItemCacheConfig {
+            ttl_seconds: 3600,
+            max_entries: 2,
+        };
+        let cache = ItemCache::new(&path, &config).unwrap();
+        let (a, b, c) = (
+            ItemKey::new(440, "1", "0"),
+            ItemKey::new(440, "2", "0"),
+            ItemKey::new(440, "3", "0"),
+        );
+
+        cache.insert(a.clone(), create_dummy_info()).unwrap();
+        cache.insert(b.clone(), create_dummy_info()).unwrap();
+        // Make `b` the least recently used, and `a` stale.
+        {
+            let mut data = cache.data.write().unwrap();
+            data.get_mut(&a).unwrap().fetched_at = 0;
+            data.get_mut(&a).unwrap().last_used = 0;
+            data.get_mut(&b).unwrap().last_used = 1;
+        }
+        assert!(cache.get(&a).is_some());
+        assert_eq!(cache.stale(10), vec![a.clone()]);
+
+        cache.insert(c.clone(), create_dummy_info()).unwrap();
+        assert!(cache.get(&a).is_some());
+        assert!(cache.get(&b).is_none());
+        assert!(cache.get(&c).is_some());
+
+        cache.refreshed(&a, None);
+        assert!(cache.stale(10).is_empty());
+    }
+
+    #[test]
+    fn test_evicts_in_batches() {
+        let path = get_temp_file_path();
+        let config = ItemCacheConfig {
+            ttl_seconds: 3600,
+            max_entries: 20,
+        };
+        let cache = ItemCache::new(&path, &config).unwrap();
+        for i in 0..20 {
+            cache
+                .insert(ItemKey::new(440, &i.to_string(), "0"), create_dummy_info())
+                .unwrap();
+            cache
+                .data
+                .write()
+                .unwrap()
+                .values_mut()
+                .for_each(|e| e.last_used = 0);
+        }
+        assert_eq!(cache.len(), 20);
+
+        // Going over the cap drops down to 90% of it, keeping the newest item.
+        let newest = ItemKey::new(440, "20", "0");
+        cache.insert(newest.clone(), create_dummy_info()).unwrap();
+        assert_eq!(cache.len(), 18);
+        assert!(cache.get(&newest).is_some());
+    }
+
+    #[test]
+    fn test_migrates_version_2_as_stale() {
+        let path = get_temp_file_path();
+        let old = CacheFile {
+            version: 2,
+            items: BTreeMap::from([("440_100_0".to_string(), create_dummy_info())]),
+            legacy: BTreeMap::new(),
+        };
+        fs::write(&path, serde_json::to_string(&old).unwrap()).unwrap();
+
+        let cache = ItemCache::new(&path, &ItemCacheConfig::default()).unwrap();
+        let key = ItemKey::new(440, "100", "0");
+        assert_eq!(cache.get(&key).unwrap().name, "Test Item");
+        assert_eq!(cache.stale(10), vec![key]);
+
+        let _ = fs::remove_file(&path);
+    }
 }
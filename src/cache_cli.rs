@@ -0,0 +1,166 @@
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use log::warn;
+
+use crate::{
+    cache::{CachedItem, ItemCache, ItemKey},
+    cli::{CacheCommand, Cli},
+    config::Config,
+    files::DataLock,
+    ratelimit::RateLimiter,
+    steam::{SteamClient, find_class_info},
+};
+
+/// Most class IDs looked up per request when prewarming.
+const PREWARM_BATCH: usize = 100;
+
+/// Runs a `cache` subcommand, saving any changes to the cache file before returning.
+/// Commands that change the cache refuse to run while TradeBell is running, as it would
+/// overwrite their changes.
+pub async fn run(cli: &Cli, config: &Config, command: CacheCommand) -> Result<()> {
+    let _lock = match command {
+        CacheCommand::List { .. } | CacheCommand::Lookup { .. } | CacheCommand::Export { .. } => {
+            None
+        }
+        CacheCommand::Purge { .. } | CacheCommand::Import { .. } | CacheCommand::Prewarm { .. } => {
+            Some(DataLock::for_data_dir(&cli.data_dir)?)
+        }
+    };
+    let cache = ItemCache::new(cli.cache_path(), &config.item_cache)?;
+    let limiter = RateLimiter::new(&config.steam_rate_limit);
+
+    match command {
+        CacheCommand::List { appid } => {
+            for (key, entry) in cache.entries() {
+                if appid.is_none_or(|appid| key.appid == appid) {
+                    println!("{}", summary(&key, &entry));
+                }
+            }
+        }
+        CacheCommand::Lookup { classid, appid } => {
+            let found: Vec<_> = cache
+                .entries()
+                .into_iter()
+                .filter(|(key, _)| {
+                    key.classid == classid && appid.is_none_or(|appid| key.appid == appid)
+                })
+                .collect();
+            if found.is_empty() {
+                anyhow::bail!("No cached item with class ID {}.", classid);
+            }
+            for (key, entry) in found {
+                println!("{}\n{}", key, serde_json::to_string_pretty(&entry)?);
+            }
+        }
+        CacheCommand::Purge { appid, older_than } => {
+            let cutoff = older_than.map(|age| (Utc::now().timestamp() as u64).saturating_sub(age));
+            let removed = cache.purge(|key, entry| {
+                appid.is_none_or(|appid| key.appid == appid)
+                    && cutoff.is_none_or(|cutoff| entry.fetched_at < cutoff)
+            })?;
+            println!("Removed {} items.", removed);
+        }
+        CacheCommand::Export { path } => {
+            let count = cache.export(&path)?;
+            println!("Exported {} items to {}.", count, path.display());
+        }
+        CacheCommand::Import { path } => {
+            let count = cache.import(&path)?;
+            println!("Imported {} items from {}.", count, path.display());
+        }
+        CacheCommand::Prewarm {
+            appid,
+            classids,
+            inventory,
+            context,
+            account,
+        } => {
+            let added = match inventory {
+                Some(steamid) => {
+                    let context = context.as_deref().unwrap_or("2");
+                    prewarm_inventory(&cache, limiter, &steamid, appid, context).await?
+                }
+                None => {
+                    let Some(account) = config.accounts.iter().find(|a| {
+                        account
+                            .as_deref()
+                            .is_none_or(|name| a.name.eq_ignore_ascii_case(name))
+                    }) else {
+                        anyhow::bail!("No account to look up items with.");
+                    };
+                    let client = SteamClient::new(account.api_key.clone(), limiter);
+                    prewarm_classids(&cache, &client, appid, &classids).await?
+                }
+            };
+            println!("Cached {} items.", added);
+        }
+    }
+
+    cache.flush()
+}
+
+/// One line per item: key, market name, type and when it was fetched.
+fn summary(key: &ItemKey, entry: &CachedItem) -> String {
+    let fetched = DateTime::from_timestamp(entry.fetched_at as i64, 0)
+        .filter(|_| entry.fetched_at > 0)
+        .map_or("unknown".to_string(), |time| {
+            time.format("%Y-%m-%d %H:%M").to_string()
+        });
+    format!(
+        "{}\t{}\t{}\tfetched {}",
+        key, entry.info.market_hash_name, entry.info.type_, fetched
+    )
+}
+
+async fn prewarm_inventory(
+    cache: &ItemCache,
+    limiter: RateLimiter,
+    steamid: &str,
+    appid: u32,
+    contextid: &str,
+) -> Result<usize> {
+    // Public inventories are read from the Steam Community, which needs no API key.
+    let client = SteamClient::new(String::new(), limiter);
+    let descriptions = client.get_inventory(steamid, appid, contextid).await?;
+
+    let count = descriptions.len();
+    for description in descriptions {
+        let key = ItemKey::new(
+            description.appid,
+            &description.classid,
+            &description.instanceid,
+        );
+        cache.insert(key, description.into())?;
+    }
+    Ok(count)
+}
+
+async fn prewarm_classids(
+    cache: &ItemCache,
+    client: &SteamClient,
+    appid: u32,
+    classids: &[String],
+) -> Result<usize> {
+    let pairs: Vec<(String, String)> = classids
+        .iter()
+        .map(|id| match id.split_once('_') {
+            Some((classid, instanceid)) => (classid.to_string(), instanceid.to_string()),
+            None => (id.clone(), "0".to_string()),
+        })
+        .collect();
+
+    let mut count = 0;
+    for batch in pairs.chunks(PREWARM_BATCH) {
+        let infos = client.get_asset_class_info(appid, batch).await?;
+        for (classid, instanceid) in batch {
+            match find_class_info(&infos, classid, instanceid) {
+                Some(info) => {
+                    cache.insert(ItemKey::new(appid, classid, instanceid), info.clone())?;
+                    count += 1;
+                }
+                None => warn!("Steam has no description for {}_{}.", classid, instanceid),
+            }
+        }
+    }
+    Ok(count)
+}
@@ -0,0 +1,229 @@
+use chrono::{DateTime, NaiveDate};
+use clap::{Parser, Subcommand};
+use log::LevelFilter;
+use std::path::PathBuf;
+
+/// Steam trade notifications for Telegram and Discord.
+#[derive(Debug, Parser)]
+#[command(name = "tradebell", version)]
+pub struct Cli {
+    /// Config file to load.
+    #[arg(long, global = true, default_value = "config.json")]
+    pub config: PathBuf,
+
+    /// Item cache file. Defaults to cache.json in the data directory.
+    #[arg(long, global = true)]
+    pub cache: Option<PathBuf>,
+
+    /// Directory holding the item cache, poller state, trade ledger and Telegram outbox.
+    #[arg(long, global = true, default_value = ".")]
+    pub data_dir: PathBuf,
+
+    /// Log level (off, error, warn, info, debug, trace). Overrides RUST_LOG.
+    #[arg(long, global = true)]
+    pub log_level: Option<LevelFilter>,
+
+    /// Check every Steam API key and the Telegram token against the live APIs before starting.
+    #[arg(long, global = true)]
+    pub probe: bool,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Debug, Clone, Subcommand)]
+pub enum Command {
+    /// Watch the configured accounts and send notifications. The default.
+    Run,
+    /// Check that the config, rules and templates load, then exit.
+    CheckConfig,
+    /// Send a test notification through every account's destinations.
+    TestNotify {
+        /// Only test this account.
+        #[arg(long)]
+        account: Option<String>,
+    },
+    /// Notify about trades completed since a date that haven't been notified yet, then exit.
+    Backfill {
+        /// A date (2024-05-01, midnight UTC), an RFC 3339 time or a Unix timestamp.
+        #[arg(long, value_parser = parse_since)]
+        since: u64,
+        /// Only backfill this account.
+        #[arg(long)]
+        account: Option<String>,
+    },
+    /// Inspect and manage the item cache. Stop TradeBell first when changing it.
+    Cache {
+        #[command(subcommand)]
+        command: CacheCommand,
+    },
+}
+
+#[derive(Debug, Clone, Subcommand)]
+pub enum CacheCommand {
+    /// List cached items.
+    List {
+        /// Only list items of this app.
+        #[arg(long)]
+        appid: Option<u32>,
+    },
+    /// Show every cached item with a class ID.
+    Lookup {
+        classid: String,
+        /// Only show items of this app.
+        #[arg(long)]
+        appid: Option<u32>,
+    },
+    /// Remove items of an app, or fetched longer ago than an age. Both together remove items
+    /// matching both.
+    #[command(group = clap::ArgGroup::new("filter").required(true).multiple(true))]
+    Purge {
+        #[arg(long, group = "filter")]
+        appid: Option<u32>,
+        /// An age like 90d, 12h, 30m or 45s.
+        #[arg(long, group = "filter", value_parser = parse_age)]
+        older_than: Option<u64>,
+    },
+    /// Write the cached items to a file.
+    Export { path: PathBuf },
+    /// Add the items in an exported file to the cache, replacing any already cached.
+    Import { path: PathBuf },
+    /// Look up items ahead of time: the given class IDs, or everything in a Steam inventory.
+    #[command(group = clap::ArgGroup::new("source").required(true))]
+    Prewarm {
+        /// App the items belong to, e.g. 440 for TF2 or 730 for CS2.
+        #[arg(long)]
+        appid: u32,
+        /// Class IDs, optionally with an instance ID as classid_instanceid.
+        #[arg(group = "source")]
+        classids: Vec<String>,
+        /// SteamID64 of a public inventory to read the items from.
+        #[arg(long, group = "source")]
+        inventory: Option<String>,
+        /// Inventory context: 2 (the default) for most games, 6 for Steam community items.
+        #[arg(long, conflicts_with = "classids")]
+        context: Option<String>,
+        /// Account whose API key looks up class IDs; the first account by default.
+        #[arg(long)]
+        account: Option<String>,
+    },
+}
+
+impl Cli {
+    pub fn cache_path(&self) -> PathBuf {
+        self.cache
+            .clone()
+            .unwrap_or_else(|| self.data_dir.join("cache.json"))
+    }
+
+    /// Path of a file kept in the data directory.
+    pub fn data_path(&self, file_name: &str) -> PathBuf {
+        self.data_dir.join(file_name)
+    }
+}
+
+/// Parses `--since` into a Unix timestamp.
+fn parse_since(value: &str) -> Result<u64, String> {
+    if let Ok(timestamp) = value.parse::<u64>() {
+        return Ok(timestamp);
+    }
+    if let Ok(time) = DateTime::parse_from_rfc3339(value) {
+        return u64::try_from(time.timestamp()).map_err(|_| "time is before 1970".to_string());
+    }
+    if let Ok(date) = NaiveDate::parse_from_str(value, "%Y-%m-%d") {
+        let time = date.and_hms_opt(0, 0, 0).unwrap_or_default().and_utc();
+        return u64::try_from(time.timestamp()).map_err(|_| "date is before 1970".to_string());
+    }
+    Err(format!(
+        "expected a date like 2024-05-01, an RFC 3339 time or a Unix timestamp, got {:?}",
+        value
+    ))
+}
+
+/// Parses an age like `90d` into seconds.
+fn parse_age(value: &str) -> Result<u64, String> {
+    let invalid = || format!("expected an age like 90d, 12h, 30m or 45s, got {:?}", value);
+    let Some((split, _)) = value.char_indices().last() else {
+        return Err(invalid());
+    };
+    let (amount, unit) = value.split_at(split);
+    let amount: u64 = amount.parse().map_err(|_| invalid())?;
+    let seconds = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        _ => return Err(invalid()),
+    };
+    amount
+        .checked_mul(seconds)
+        .ok_or_else(|| format!("{:?} is too long an age", value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_since() {
+        assert_eq!(parse_since("1714521600"), Ok(1714521600));
+        assert_eq!(parse_since("2024-05-01"), Ok(1714521600));
+        assert_eq!(parse_since("2024-05-01T02:00:00+02:00"), Ok(1714521600));
+        assert!(parse_since("yesterday").is_err());
+    }
+
+    #[test]
+    fn test_parse_age() {
+        assert_eq!(parse_age("45s"), Ok(45));
+        assert_eq!(parse_age("12h"), Ok(43200));
+        assert_eq!(parse_age("90d"), Ok(7776000));
+        assert!(parse_age("90").is_err());
+        assert!(parse_age("d").is_err());
+        assert!(parse_age("").is_err());
+        assert!(parse_age("18446744073709551615d").is_err());
+    }
+
+    #[test]
+    fn test_cache_commands() {
+        let cli = Cli::parse_from(["tradebell", "cache", "purge", "--appid", "440"]);
+        assert!(matches!(
+            cli.command,
+            Some(Command::Cache {
+                command: CacheCommand::Purge {
+                    appid: Some(440),
+                    older_than: None
+                }
+            })
+        ));
+
+        assert!(Cli::try_parse_from(["tradebell", "cache", "purge"]).is_err());
+        assert!(Cli::try_parse_from(["tradebell", "cache", "prewarm", "--appid", "440"]).is_err());
+        assert!(
+            Cli::try_parse_from([
+                "tradebell",
+                "cache",
+                "prewarm",
+                "--appid",
+                "440",
+                "--context",
+                "6",
+                "101785959"
+            ])
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn test_paths() {
+        let cli = Cli::parse_from(["tradebell", "--data-dir", "/data", "check-config"]);
+        assert_eq!(cli.cache_path(), PathBuf::from("/data/cache.json"));
+        assert_eq!(
+            cli.data_path("state.json"),
+            PathBuf::from("/data/state.json")
+        );
+
+        let cli = Cli::parse_from(["tradebell", "--cache", "/tmp/items.json"]);
+        assert_eq!(cli.cache_path(), PathBuf::from("/tmp/items.json"));
+        assert!(cli.command.is_none());
+    }
+}
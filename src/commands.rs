@@ -0,0 +1,262 @@
+use chrono::{DateTime, Utc};
+use log::{error, info, warn};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+use tokio::time::sleep;
+
+use crate::{
+    files::write_atomic, ledger::Ledger, message::escape_html, status::StatusRegistry,
+    telegram::TelegramBot,
+};
+
+/// How long a single `getUpdates` call waits for new messages.
+const LONG_POLL_TIMEOUT_SECS: u64 = 50;
+const DEFAULT_LAST_TRADES: usize = 5;
+const MAX_LAST_TRADES: usize = 50;
+
+#[derive(Debug, PartialEq)]
+pub enum Command {
+    Status,
+    Accounts,
+    Pause(String),
+    Resume(String),
+    Last(usize),
+    Help,
+    Unknown(String),
+}
+
+/// Parses a bot command, ignoring a trailing `@botname` on the command itself.
+/// Returns `None` for messages that aren't commands.
+pub fn parse_command(text: &str) -> Option<Command> {
+    let text = text.trim();
+    let rest = text.strip_prefix('/')?;
+    let (command, args) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+    let command = command.split('@').next().unwrap_or_default().to_lowercase();
+    let args = args.trim();
+
+    Some(match command.as_str() {
+        "status" => Command::Status,
+        "accounts" => Command::Accounts,
+        "pause" => Command::Pause(args.to_string()),
+        "resume" => Command::Resume(args.to_string()),
+        "last" => Command::Last(
+            args.parse()
+                .unwrap_or(DEFAULT_LAST_TRADES)
+                .clamp(1, MAX_LAST_TRADES),
+        ),
+        "help" | "start" => Command::Help,
+        other => Command::Unknown(other.to_string()),
+    })
+}
+
+/// Answers commands sent to the bot from the configured chat, forever. The offset of the next
+/// update is kept in `offset_path`, so that a restart neither answers a command twice nor
+/// misses one sent while stopped.
+pub async fn run(
+    bot: Arc<TelegramBot>,
+    status: StatusRegistry,
+    ledger: Ledger,
+    offset_path: PathBuf,
+) {
+    info!("Listening for Telegram bot commands...");
+    let mut offset = load_offset(&offset_path);
+
+    loop {
+        let updates = match bot.get_updates(offset, LONG_POLL_TIMEOUT_SECS).await {
+            Ok(updates) => updates,
+            Err(e) => {
+                error!("Failed to fetch Telegram updates: {}", e);
+                sleep(Duration::from_secs(10)).await;
+                continue;
+            }
+        };
+
+        let Some(last) = updates.iter().map(|u| u.update_id).max() else {
+            continue;
+        };
+        offset = offset.max(last + 1);
+        if let Err(e) = write_atomic(&offset_path, &offset.to_string(), "Telegram offset") {
+            error!("{:#}", e);
+        }
+
+        for update in updates {
+            let Some(message) = update.message else {
+                continue;
+            };
+            let Some(command) = message.text.as_deref().and_then(parse_command) else {
+                continue;
+            };
+            // Only the configured chat may operate the watcher.
+            if message.chat.id.to_string() != bot.chat_id() {
+                warn!(
+                    "Ignoring Telegram command from unknown chat {}",
+                    message.chat.id
+                );
+                continue;
+            }
+
+            let reply = handle_command(command, &status, &ledger);
+            if let Err(e) = bot.send_notification(&reply) {
+                error!("Failed to queue Telegram command reply: {}", e);
+            }
+        }
+    }
+}
+
+/// The offset saved by a previous run, or 0 to start with whatever Telegram still holds.
+fn load_offset(path: &Path) -> i64 {
+    fs::read_to_string(path)
+        .ok()
+        .and_then(|content| content.trim().parse().ok())
+        .unwrap_or(0)
+}
+
+/// Builds the HTML reply for a command.
+pub fn handle_command(command: Command, status: &StatusRegistry, ledger: &Ledger) -> String {
+    match command {
+        Command::Status => {
+            let accounts = status.snapshot();
+            if accounts.is_empty() {
+                return "No accounts configured.".to_string();
+            }
+
+            let mut lines = Vec::new();
+            for (name, account) in accounts {
+                let state = if account.paused { "paused" } else { "running" };
+                lines.push(format!("<b>{}</b> ({})", escape_html(&name), state));
+                lines.push(format!(
+                    "Last poll: {}",
+                    account.last_poll.map_or("never".to_string(), format_time)
+                ));
+                if let Some((time, error)) = account.last_error {
+                    lines.push(format!(
+                        "Last error: {} at {}",
+                        escape_html(&error),
+                        format_time(time)
+                    ));
+                }
+                lines.push(String::new());
+            }
+            lines.join("\n").trim_end().to_string()
+        }
+        Command::Accounts => {
+            let accounts = status.snapshot();
+            if accounts.is_empty() {
+                return "No accounts configured.".to_string();
+            }
+
+            let mut lines = vec!["<b>Accounts:</b>".to_string()];
+            for (name, account) in accounts {
+                let suffix = if account.paused { " (paused)" } else { "" };
+                lines.push(format!("- {}{}", escape_html(&name), suffix));
+            }
+            lines.join("\n")
+        }
+        Command::Pause(account) | Command::Resume(account) if account.is_empty() => {
+            "Usage: /pause &lt;account&gt; or /resume &lt;account&gt;".to_string()
+        }
+        Command::Pause(account) => match status.set_paused(&account, true) {
+            Some(name) => {
+                info!("[{}] Paused via Telegram.", name);
+                format!("Paused <b>{}</b>.", escape_html(&name))
+            }
+            None => format!("Unknown account: {}", escape_html(&account)),
+        },
+        Command::Resume(account) => match status.set_paused(&account, false) {
+            Some(name) => {
+                info!("[{}] Resumed via Telegram.", name);
+                format!("Resumed <b>{}</b>.", escape_html(&name))
+            }
+            None => format!("Unknown account: {}", escape_html(&account)),
+        },
+        Command::Last(limit) => match ledger.recent_trades(limit) {
+            Ok(trades) if trades.is_empty() => "No trades recorded yet.".to_string(),
+            Ok(trades) => {
+                let mut lines = vec![format!("<b>Last {} trades:</b>", trades.len())];
+                for trade in trades {
+                    lines.push(format!(
+                        "{} | <b>{}</b> | Trade {} | +{} / -{} items | <a href=\"https://steamcommunity.com/profiles/{}\">partner</a>",
+                        format_time(trade.time_init),
+                        escape_html(&trade.account),
+                        trade.trade_id,
+                        trade.items_received,
+                        trade.items_given,
+                        trade.partner_steamid
+                    ));
+                }
+                lines.join("\n")
+            }
+            Err(e) => format!(
+                "Failed to read the trade ledger: {}",
+                escape_html(&e.to_string())
+            ),
+        },
+        Command::Help => [
+            "<b>TradeBell commands:</b>",
+            "/status - Last poll time and errors per account",
+            "/accounts - List watched accounts",
+            "/pause &lt;account&gt; - Stop polling an account",
+            "/resume &lt;account&gt; - Resume polling an account",
+            "/last &lt;n&gt; - Show the n most recent trades",
+            "/help - Show this message",
+        ]
+        .join("\n"),
+        Command::Unknown(command) => format!(
+            "Unknown command: /{}. Send /help for a list of commands.",
+            escape_html(&command)
+        ),
+    }
+}
+
+fn format_time(timestamp: u64) -> String {
+    DateTime::<Utc>::from_timestamp(timestamp as i64, 0)
+        .map(|t| t.format("%Y-%m-%d %H:%M:%S UTC").to_string())
+        .unwrap_or_else(|| timestamp.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_command() {
+        assert_eq!(parse_command("/status"), Some(Command::Status));
+        assert_eq!(parse_command("/status@TradeBellBot"), Some(Command::Status));
+        assert_eq!(
+            parse_command("/pause Main Account"),
+            Some(Command::Pause("Main Account".to_string()))
+        );
+        assert_eq!(parse_command("/last 3"), Some(Command::Last(3)));
+        assert_eq!(
+            parse_command("/last"),
+            Some(Command::Last(DEFAULT_LAST_TRADES))
+        );
+        assert_eq!(
+            parse_command("/last 1000"),
+            Some(Command::Last(MAX_LAST_TRADES))
+        );
+        assert_eq!(
+            parse_command("/frobnicate"),
+            Some(Command::Unknown("frobnicate".to_string()))
+        );
+        assert_eq!(parse_command("hello"), None);
+    }
+
+    #[test]
+    fn test_pause_command_updates_registry() {
+        let status = StatusRegistry::default();
+        status.register("Bot1");
+        let ledger = Ledger::open_in_memory().unwrap();
+
+        let reply = handle_command(Command::Pause("bot1".to_string()), &status, &ledger);
+        assert_eq!(reply, "Paused <b>Bot1</b>.");
+        assert!(status.is_paused("Bot1"));
+
+        let reply = handle_command(Command::Resume("Bot2".to_string()), &status, &ledger);
+        assert_eq!(reply, "Unknown account: Bot2");
+    }
+}
@@ -1,34 +1,307 @@
 use anyhow::{Context, Result};
-use serde::{Deserialize, Serialize};
-use std::{fs, path::Path};
+use serde::{Deserialize, Deserializer, Serialize};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    env::{ENV_PREFIX, apply_overrides},
+    models::TradeOfferState,
+    validate::validate,
+};
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct Config {
+    #[serde(default)]
     pub telegram_token: String,
+    #[serde(default, deserialize_with = "string_or_number")]
     pub telegram_chat_id: String,
     pub polling_interval_seconds: u64,
     pub accounts: Vec<AccountConfig>,
+    #[serde(default)]
+    pub notifier: NotifierConfig,
+    /// Also notify about new incoming offers that are still awaiting a response.
+    #[serde(default)]
+    pub notify_incoming_offers: bool,
+    /// Trade offer state transitions that trigger a notification.
+    #[serde(default)]
+    pub state_notifications: Vec<StateNotification>,
+    /// Alert when a sent offer is canceled and re-sent with the same items to another account.
+    #[serde(default = "default_true")]
+    pub detect_trade_redirects: bool,
+    /// Enables Community Market valuation of trades when present.
+    #[serde(default)]
+    pub pricing: Option<PricingConfig>,
+    /// Answer bot commands sent from `telegram_chat_id`.
+    #[serde(default)]
+    pub telegram_commands: bool,
+    /// Filters evaluated before notifying; the first matching rule decides what happens.
+    #[serde(default)]
+    pub rules: Vec<RuleConfig>,
+    #[serde(default)]
+    pub item_cache: ItemCacheConfig,
+    #[serde(default)]
+    pub steam_rate_limit: SteamRateLimitConfig,
+}
+
+/// A limit on Steam requests, shared by all accounts.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct SteamRateLimitConfig {
+    #[serde(default = "default_requests_per_minute")]
+    pub requests_per_minute: u32,
+    /// Requests that may be made back to back after a quiet spell.
+    #[serde(default = "default_burst")]
+    pub burst: u32,
+}
+
+impl Default for SteamRateLimitConfig {
+    fn default() -> Self {
+        Self {
+            requests_per_minute: default_requests_per_minute(),
+            burst: default_burst(),
+        }
+    }
+}
+
+/// Limits on the cache of item descriptions.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct ItemCacheConfig {
+    /// Descriptions older than this are fetched again in the background; 0 never refreshes.
+    #[serde(default = "default_item_cache_ttl")]
+    pub ttl_seconds: u64,
+    /// The least recently used descriptions are dropped beyond this many.
+    #[serde(default = "default_item_cache_max_entries")]
+    pub max_entries: usize,
+}
+
+impl Default for ItemCacheConfig {
+    fn default() -> Self {
+        Self {
+            ttl_seconds: default_item_cache_ttl(),
+            max_entries: default_item_cache_max_entries(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct PricingConfig {
+    /// Steam currency code, e.g. 1 = USD, 2 = GBP, 3 = EUR.
+    #[serde(default = "default_currency")]
+    pub currency: u32,
+    #[serde(default = "default_price_cache_ttl")]
+    pub cache_ttl_seconds: u64,
+}
+
+/// Chat IDs are strings, but are often written as numbers.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum StringOrNumber {
+    String(String),
+    Number(i64),
+}
+
+impl From<StringOrNumber> for String {
+    fn from(value: StringOrNumber) -> Self {
+        match value {
+            StringOrNumber::String(s) => s,
+            StringOrNumber::Number(n) => n.to_string(),
+        }
+    }
+}
+
+fn string_or_number<'de, D: Deserializer<'de>>(deserializer: D) -> Result<String, D::Error> {
+    StringOrNumber::deserialize(deserializer).map(String::from)
+}
+
+fn optional_string_or_number<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<String>, D::Error> {
+    Ok(Option::<StringOrNumber>::deserialize(deserializer)?.map(String::from))
+}
+
+fn default_currency() -> u32 {
+    1
+}
+
+fn default_price_cache_ttl() -> u64 {
+    60 * 60
+}
+
+fn default_item_cache_ttl() -> u64 {
+    30 * 24 * 60 * 60
+}
+
+fn default_item_cache_max_entries() -> usize {
+    100_000
+}
+
+fn default_requests_per_minute() -> u32 {
+    60
+}
+
+fn default_burst() -> u32 {
+    10
+}
+
+fn default_true() -> bool {
+    true
+}
+
+/// Notify when an offer moves into `to`, optionally only when coming from `from`.
+/// Omitting `from` also matches offers seen for the first time in the `to` state.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct StateNotification {
+    #[serde(default)]
+    pub from: Option<TradeOfferState>,
+    pub to: TradeOfferState,
+}
+
+impl StateNotification {
+    pub fn matches(&self, from: Option<TradeOfferState>, to: TradeOfferState) -> bool {
+        self.to == to && self.from.is_none_or(|expected| from == Some(expected))
+    }
+}
+
+/// Which backend trade notifications are delivered through.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum NotifierConfig {
+    /// Sends through the bot configured by `telegram_token`.
+    Telegram {
+        /// Chat to send to; defaults to `telegram_chat_id`.
+        #[serde(default, deserialize_with = "optional_string_or_number")]
+        chat_id: Option<String>,
+        /// Forum topic within the chat.
+        #[serde(default)]
+        thread_id: Option<i64>,
+        /// Deliver without a notification sound.
+        #[serde(default)]
+        silent: bool,
+        /// Message template file; the built-in layout is used when unset.
+        #[serde(default)]
+        template: Option<PathBuf>,
+    },
+    Discord {
+        webhook_url: String,
+        #[serde(default)]
+        template: Option<PathBuf>,
+    },
+}
+
+impl Default for NotifierConfig {
+    fn default() -> Self {
+        Self::Telegram {
+            chat_id: None,
+            thread_id: None,
+            silent: false,
+            template: None,
+        }
+    }
+}
+
+impl NotifierConfig {
+    pub fn template(&self) -> Option<&Path> {
+        match self {
+            Self::Telegram { template, .. } | Self::Discord { template, .. } => template.as_deref(),
+        }
+    }
+}
+
+/// Conditions on a notification and what to do when they all hold. Conditions that are left out
+/// always hold. `appid`, `name` and `type` are matched against the items in the trade.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct RuleConfig {
+    /// Item belongs to one of these apps.
+    #[serde(default)]
+    pub appid: Vec<u32>,
+    /// Regex matched against the item's market hash name.
+    #[serde(default)]
+    pub name: Option<String>,
+    /// Regex matched against the item's type, e.g. "Craft Item".
+    #[serde(default, rename = "type")]
+    pub item_type: Option<String>,
+    /// Require every item to match the item conditions, instead of at least one.
+    #[serde(default)]
+    pub all_items: bool,
+    /// SteamID64s of the trade partner.
+    #[serde(default)]
+    pub partner: Vec<String>,
+    /// Bounds on the number of items changing hands, both sides together.
+    #[serde(default)]
+    pub min_items: Option<usize>,
+    #[serde(default)]
+    pub max_items: Option<usize>,
+    /// Bounds on the market value of the more valuable side, in the pricing currency.
+    /// Never hold when pricing is disabled.
+    #[serde(default)]
+    pub min_value: Option<f64>,
+    #[serde(default)]
+    pub max_value: Option<f64>,
+    pub action: RuleAction,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum RuleAction {
+    /// Notify as usual.
+    Include,
+    /// Don't notify.
+    Exclude,
+    /// Notify with high priority.
+    Priority,
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct AccountConfig {
     pub name: String,
     pub api_key: String,
+    /// Where this account's notifications go; the global `notifier` when empty.
+    #[serde(default)]
+    pub notifiers: Vec<NotifierConfig>,
 }
 
 impl Config {
-    pub fn load() -> Result<Self> {
-        let config_path = Path::new("config.json");
+    /// Loads the config file, then applies `TRADEBELL_*` environment overrides on top, and
+    /// validates the result. The file may be missing if the environment provides everything.
+    pub fn load(config_path: &Path) -> Result<Self> {
+        let vars = std::env::vars_os()
+            .filter_map(|(k, v)| Some((k.into_string().ok()?, v.into_string().ok()?)))
+            .filter(|(k, _)| k.starts_with(ENV_PREFIX))
+            .collect();
+        Self::load_with_env(config_path, vars)
+    }
 
-        if !config_path.exists() {
-            anyhow::bail!("config.json not found. Please create one based on the documentation.");
-        }
+    fn load_with_env(config_path: &Path, vars: Vec<(String, String)>) -> Result<Self> {
+        let mut value = if config_path.exists() {
+            let content = fs::read_to_string(config_path)
+                .with_context(|| format!("Failed to read {}", config_path.display()))?;
+            serde_json::from_str(&content)
+                .with_context(|| format!("Failed to parse {}", config_path.display()))?
+        } else if vars.is_empty() {
+            anyhow::bail!(
+                "{} not found. Please create one based on the documentation.",
+                config_path.display()
+            );
+        } else {
+            serde_json::Value::Object(Default::default())
+        };
 
-        let content = fs::read_to_string(config_path).context("Failed to read config.json")?;
+        let overridden = apply_overrides(&mut value, vars)?;
 
-        let config: Config =
-            serde_json::from_str(&content).context("Failed to parse config.json")?;
+        let config: Config = serde_json::from_value(value).with_context(|| {
+            if overridden > 0 {
+                format!(
+                    "Failed to parse {} with {} environment overrides",
+                    config_path.display(),
+                    overridden
+                )
+            } else {
+                format!("Failed to parse {}", config_path.display())
+            }
+        })?;
 
+        validate(&config).with_context(|| format!("Invalid {}", config_path.display()))?;
         Ok(config)
     }
 }
@@ -57,5 +330,146 @@ mod tests {
         assert_eq!(config.accounts.len(), 1);
         assert_eq!(config.accounts[0].name, "Bot1");
         assert_eq!(config.accounts[0].api_key, "KEY123");
+        assert_eq!(config.notifier, NotifierConfig::default());
+        assert!(!config.notify_incoming_offers);
+        assert!(config.detect_trade_redirects);
+        assert!(config.pricing.is_none());
+        assert!(!config.telegram_commands);
+        assert_eq!(config.item_cache, ItemCacheConfig::default());
+        assert_eq!(config.steam_rate_limit, SteamRateLimitConfig::default());
+    }
+
+    #[test]
+    fn test_state_notifications() {
+        let json_data = r#"{
+            "polling_interval_seconds": 30,
+            "accounts": [],
+            "state_notifications": [
+                { "from": "Active", "to": "Declined" },
+                { "to": "InEscrow" }
+            ]
+        }"#;
+
+        let config: Config = serde_json::from_str(json_data).expect("Failed to parse Config");
+        let rules = &config.state_notifications;
+
+        assert!(rules[0].matches(Some(TradeOfferState::Active), TradeOfferState::Declined));
+        assert!(!rules[0].matches(None, TradeOfferState::Declined));
+        assert!(rules[1].matches(None, TradeOfferState::InEscrow));
+        assert!(rules[1].matches(Some(TradeOfferState::Active), TradeOfferState::InEscrow));
+        assert!(!rules[1].matches(Some(TradeOfferState::InEscrow), TradeOfferState::Accepted));
+    }
+
+    #[test]
+    fn test_account_notifiers() {
+        let json_data = r#"{
+            "telegram_token": "123:ABC",
+            "telegram_chat_id": "-999",
+            "polling_interval_seconds": 30,
+            "accounts": [
+                { "name": "Main", "api_key": "KEY1" },
+                {
+                    "name": "Storage",
+                    "api_key": "KEY2",
+                    "notifiers": [
+                        { "type": "telegram", "chat_id": "-555", "thread_id": 12, "silent": true },
+                        { "type": "discord", "webhook_url": "https://discord.com/api/webhooks/1/abc" }
+                    ]
+                }
+            ]
+        }"#;
+
+        let config: Config = serde_json::from_str(json_data).expect("Failed to parse Config");
+
+        assert!(config.accounts[0].notifiers.is_empty());
+        assert_eq!(config.accounts[1].notifiers.len(), 2);
+        assert_eq!(
+            config.accounts[1].notifiers[0],
+            NotifierConfig::Telegram {
+                chat_id: Some("-555".to_string()),
+                thread_id: Some(12),
+                silent: true,
+                template: None,
+            }
+        );
+    }
+
+    #[test]
+    fn test_load_from_environment_only() {
+        let config = Config::load_with_env(
+            Path::new("/nonexistent/config.json"),
+            vec![
+                (
+                    "TRADEBELL_TELEGRAM_TOKEN".to_string(),
+                    "123:ABC".to_string(),
+                ),
+                ("TRADEBELL_TELEGRAM_CHAT_ID".to_string(), "-999".to_string()),
+                (
+                    "TRADEBELL_POLLING_INTERVAL_SECONDS".to_string(),
+                    "30".to_string(),
+                ),
+                (
+                    "TRADEBELL_ACCOUNTS__0__NAME".to_string(),
+                    "Main".to_string(),
+                ),
+                (
+                    "TRADEBELL_ACCOUNTS__0__API_KEY".to_string(),
+                    "0123456789ABCDEF0123456789ABCDEF".to_string(),
+                ),
+            ],
+        )
+        .expect("Failed to load config from the environment");
+
+        assert_eq!(config.telegram_token, "123:ABC");
+        assert_eq!(config.telegram_chat_id, "-999");
+        assert_eq!(
+            config.accounts[0].api_key,
+            "0123456789ABCDEF0123456789ABCDEF"
+        );
+
+        assert!(Config::load_with_env(Path::new("/nonexistent/config.json"), vec![]).is_err());
+    }
+
+    #[test]
+    fn test_pricing_defaults() {
+        let json_data = r#"{
+            "polling_interval_seconds": 30,
+            "accounts": [],
+            "pricing": {}
+        }"#;
+
+        let config: Config = serde_json::from_str(json_data).expect("Failed to parse Config");
+
+        assert_eq!(
+            config.pricing,
+            Some(PricingConfig {
+                currency: 1,
+                cache_ttl_seconds: 3600
+            })
+        );
+    }
+
+    #[test]
+    fn test_config_discord_notifier() {
+        let json_data = r#"{
+            "polling_interval_seconds": 30,
+            "accounts": [],
+            "notifier": {
+                "type": "discord",
+                "webhook_url": "https://discord.com/api/webhooks/1/abc",
+                "template": "templates/discord.md.j2"
+            }
+        }"#;
+
+        let config: Config = serde_json::from_str(json_data).expect("Failed to parse Config");
+
+        assert!(config.telegram_token.is_empty());
+        assert_eq!(
+            config.notifier,
+            NotifierConfig::Discord {
+                webhook_url: "https://discord.com/api/webhooks/1/abc".to_string(),
+                template: Some(PathBuf::from("templates/discord.md.j2")),
+            }
+        );
     }
 }
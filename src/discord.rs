@@ -0,0 +1,276 @@
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use log::warn;
+use reqwest::{Client, StatusCode};
+use serde::Deserialize;
+use serde_json::json;
+use std::time::Duration;
+use tokio::time::sleep;
+
+use crate::{
+    notifier::{Notifier, Priority, TradeEvent},
+    template::Template,
+};
+
+/// Discord rejects message content longer than this many characters.
+const MAX_CONTENT_LEN: usize = 2000;
+/// Room left on the first line of each part for a label like " (12/34)".
+const PART_LABEL_RESERVE: usize = 12;
+/// Room left at the top of the first part for the `@here` mention.
+const PING_RESERVE: usize = 6;
+/// A header that would leave less room than this for the body of each part is not repeated.
+const MIN_BODY_LEN: usize = 512;
+/// The first line of a template longer than this isn't used as a header.
+const MAX_TEMPLATE_HEADER_LEN: usize = 256;
+/// How many times a part is retried after hitting the rate limit before giving up.
+const MAX_RATE_LIMIT_RETRIES: u32 = 5;
+/// Used when a 429 response doesn't say how long to wait.
+const DEFAULT_RETRY_AFTER: Duration = Duration::from_secs(5);
+
+pub struct DiscordWebhook {
+    webhook_url: String,
+    client: Client,
+    template: Option<Template>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RateLimitResponse {
+    /// Seconds to wait, with a fractional part.
+    retry_after: Option<f64>,
+}
+
+impl DiscordWebhook {
+    pub fn new(webhook_url: String, template: Option<Template>) -> Self {
+        Self {
+            webhook_url,
+            client: Client::new(),
+            template,
+        }
+    }
+
+    /// Posts a message to the webhook, split into parts if it is too long, repeating `header`,
+    /// if any, at the top of every part. With `ping`, the first part mentions `@here`.
+    pub async fn send_message(&self, header: Option<&str>, body: &str, ping: bool) -> Result<()> {
+        for (i, part) in split_content(header, body).into_iter().enumerate() {
+            if ping && i == 0 {
+                self.post(&format!("@here\n{}", part), true).await?;
+            } else {
+                self.post(&part, false).await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Posts a single part, waiting and retrying when Discord's rate limit is hit.
+    async fn post(&self, content: &str, ping: bool) -> Result<()> {
+        let payload = if ping {
+            json!({
+                "content": content,
+                "allowed_mentions": { "parse": ["everyone"] }
+            })
+        } else {
+            json!({
+                "content": content,
+                "allowed_mentions": { "parse": [] }
+            })
+        };
+
+        let mut retries = 0;
+        loop {
+            let response = self
+                .client
+                .post(&self.webhook_url)
+                .json(&payload)
+                .send()
+                .await
+                // The webhook URL is the credential, so keep it out of the error.
+                .map_err(reqwest::Error::without_url)
+                .context("Failed to send Discord webhook request")?;
+
+            let status = response.status();
+            if status.is_success() {
+                return Ok(());
+            }
+
+            let error_text = response
+                .text()
+                .await
+                .unwrap_or_else(|_| "Unknown error".to_string());
+            if status == StatusCode::TOO_MANY_REQUESTS && retries < MAX_RATE_LIMIT_RETRIES {
+                let delay = retry_after(&error_text);
+                warn!(
+                    "Discord rate limit hit, retrying in {:.1}s",
+                    delay.as_secs_f64()
+                );
+                sleep(delay).await;
+                retries += 1;
+                continue;
+            }
+            anyhow::bail!("Discord webhook error ({}): {}", status, error_text);
+        }
+    }
+}
+
+/// How long a 429 response asks to wait before trying again.
+fn retry_after(body: &str) -> Duration {
+    serde_json::from_str::<RateLimitResponse>(body)
+        .ok()
+        .and_then(|r| r.retry_after)
+        .filter(|secs| secs.is_finite() && *secs >= 0.0)
+        .map_or(DEFAULT_RETRY_AFTER, Duration::from_secs_f64)
+}
+
+/// Splits a message into parts that fit Discord's length limit. Splits happen between lines
+/// where possible; a line that is too long by itself is cut between characters, never right
+/// after an escaping backslash. When there is more than one part, each is labelled "(1/3)"
+/// after the header. A header too long to repeat is sent once, as the start of the body.
+fn split_content(header: Option<&str>, body: &str) -> Vec<String> {
+    let header_len = header.map_or(0, |h| char_len(h) + 1);
+    let limit = MAX_CONTENT_LEN
+        .checked_sub(header_len + PART_LABEL_RESERVE + PING_RESERVE)
+        .filter(|&limit| limit >= MIN_BODY_LEN);
+    let Some(limit) = limit else {
+        return split_content(None, &format!("{}\n{}", header.unwrap_or_default(), body));
+    };
+
+    let mut chunks = Vec::new();
+    let mut current = String::new();
+    for line in body.lines() {
+        for piece in split_long_line(line, limit) {
+            if current.is_empty() && piece.trim().is_empty() {
+                // Don't start a part with blank lines.
+                continue;
+            }
+            if !current.is_empty() && char_len(&current) + 1 + char_len(&piece) > limit {
+                chunks.push(std::mem::take(&mut current).trim_end().to_string());
+                if piece.trim().is_empty() {
+                    continue;
+                }
+            }
+            if !current.is_empty() {
+                current.push('\n');
+            }
+            current.push_str(&piece);
+        }
+    }
+    if !current.is_empty() || chunks.is_empty() {
+        chunks.push(current);
+    }
+
+    let total = chunks.len();
+    chunks
+        .into_iter()
+        .enumerate()
+        .map(|(i, chunk)| {
+            let label = if total > 1 {
+                format!(" ({}/{})", i + 1, total)
+            } else {
+                String::new()
+            };
+            match header {
+                Some(header) => format!("{}{}\n{}", header, label, chunk),
+                None if total > 1 => format!("{}\n{}", label.trim_start(), chunk),
+                None => chunk,
+            }
+        })
+        .collect()
+}
+
+/// Cuts a single line into pieces no longer than `limit` characters.
+fn split_long_line(line: &str, limit: usize) -> Vec<String> {
+    let mut pieces = Vec::new();
+    let mut current = String::new();
+    let mut len = 0;
+    for c in line.chars() {
+        // Cutting after a backslash would leave it escaping nothing.
+        if len >= limit && !current.ends_with('\\') {
+            pieces.push(std::mem::take(&mut current));
+            len = 0;
+        }
+        current.push(c);
+        len += 1;
+    }
+    pieces.push(current);
+    pieces
+}
+
+fn char_len(text: &str) -> usize {
+    text.chars().count()
+}
+
+#[async_trait]
+impl Notifier for DiscordWebhook {
+    async fn send(&self, event: &TradeEvent) -> Result<()> {
+        // The first line of a template is the header repeated on every part of a split message,
+        // unless it is too long.
+        let (header, body) = match self.template.as_ref().and_then(|t| t.render_or_warn(event)) {
+            Some(rendered) => match rendered.split_once('\n') {
+                Some((header, body)) if char_len(header) <= MAX_TEMPLATE_HEADER_LEN => {
+                    (Some(header.to_string()), body.to_string())
+                }
+                _ => (None, rendered),
+            },
+            None => {
+                let message = event.to_message();
+                (Some(message.header.to_markdown()), message.body_markdown())
+            }
+        };
+
+        self.send_message(header.as_deref(), &body, event.priority == Priority::High)
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_short_content_is_untouched() {
+        assert_eq!(
+            split_content(Some("**Account: Bot1**"), "Trade ID: 1\n- Key"),
+            vec!["**Account: Bot1**\nTrade ID: 1\n- Key"]
+        );
+    }
+
+    #[test]
+    fn test_split_long_content_on_lines() {
+        let body = (0..300)
+            .map(|i| format!("- {}x Item number {}", i, i))
+            .collect::<Vec<_>>()
+            .join("\n");
+        let parts = split_content(Some("**Account: Bot1**"), &body);
+
+        assert!(parts.len() > 1);
+        for (i, part) in parts.iter().enumerate() {
+            assert!(char_len(part) + PING_RESERVE <= MAX_CONTENT_LEN);
+            assert!(part.starts_with(&format!(
+                "**Account: Bot1** ({}/{})\n- ",
+                i + 1,
+                parts.len()
+            )));
+        }
+        let rejoined: Vec<&str> = parts.iter().flat_map(|p| p.lines().skip(1)).collect();
+        assert_eq!(rejoined.join("\n"), body);
+    }
+
+    #[test]
+    fn test_split_overlong_line_keeps_escapes() {
+        let line = "\\*".repeat(MAX_CONTENT_LEN);
+        let parts = split_content(None, &line);
+
+        assert!(parts.len() > 1);
+        for part in &parts {
+            assert!(char_len(part) <= MAX_CONTENT_LEN);
+            let text = part.split_once('\n').unwrap().1;
+            assert!(text.starts_with('\\') && text.ends_with('*'));
+        }
+    }
+
+    #[test]
+    fn test_retry_after_from_response() {
+        let body = r#"{"message":"You are being rate limited.","retry_after":0.64,"global":false}"#;
+        assert_eq!(retry_after(body), Duration::from_millis(640));
+        assert_eq!(retry_after("<html>"), DEFAULT_RETRY_AFTER);
+    }
+}
@@ -0,0 +1,238 @@
+use anyhow::{Context, Result};
+use serde_json::{Map, Value};
+use std::{collections::BTreeMap, fs};
+
+/// Environment variables starting with this override config fields.
+pub const ENV_PREFIX: &str = "TRADEBELL_";
+/// Suffix for variables naming a file that holds the value, e.g. a Docker secret.
+const FILE_SUFFIX: &str = "_FILE";
+/// Separates nested fields and list indices, e.g. `TRADEBELL_ACCOUNTS__0__API_KEY`.
+const PATH_SEPARATOR: &str = "__";
+/// Fields that always hold text, even when the value looks like a number, e.g. an all-digit
+/// name or a numeric chat ID.
+const STRING_FIELDS: [&str; 8] = [
+    "telegram_token",
+    "telegram_chat_id",
+    "name",
+    "api_key",
+    "chat_id",
+    "webhook_url",
+    "template",
+    "type",
+];
+/// Lists whose entries always hold text.
+const STRING_LISTS: [&str; 1] = ["partner"];
+
+/// Applies `TRADEBELL_*` overrides from `vars` to a parsed config, before it is deserialized.
+/// Returns how many fields were overridden.
+pub fn apply_overrides<I>(config: &mut Value, vars: I) -> Result<usize>
+where
+    I: IntoIterator<Item = (String, String)>,
+{
+    // Field path -> (variable name, value), so a variable and its `_FILE` form can't both be set.
+    let mut overrides: BTreeMap<String, (String, String)> = BTreeMap::new();
+
+    for (name, value) in vars {
+        let Some(field) = name.strip_prefix(ENV_PREFIX) else {
+            continue;
+        };
+        let (field, value) = match field.strip_suffix(FILE_SUFFIX) {
+            Some(field) => {
+                let contents = fs::read_to_string(&value)
+                    .with_context(|| format!("Failed to read {} ({})", name, value))?;
+                (field, contents.trim_end_matches(['\r', '\n']).to_string())
+            }
+            None => (field, value),
+        };
+
+        if let Some((other, _)) = overrides.get(field) {
+            anyhow::bail!("Both {} and {} are set; use only one.", other, name);
+        }
+        overrides.insert(field.to_string(), (name, value));
+    }
+
+    // Apply whole objects and lists before the fields inside them.
+    let mut overrides: Vec<_> = overrides.into_iter().collect();
+    overrides.sort_by_key(|(field, _)| field.matches(PATH_SEPARATOR).count());
+
+    for (field, (name, value)) in &overrides {
+        let path: Vec<&str> = field.split(PATH_SEPARATOR).collect();
+        set_path(config, &path, value).with_context(|| format!("Invalid override {}", name))?;
+    }
+
+    Ok(overrides.len())
+}
+
+fn set_path(root: &mut Value, path: &[&str], raw: &str) -> Result<()> {
+    let mut current = root;
+
+    for segment in path {
+        if segment.is_empty() {
+            anyhow::bail!("empty field name");
+        }
+
+        if let Ok(index) = segment.parse::<usize>() {
+            if current.is_null() {
+                *current = Value::Array(Vec::new());
+            }
+            let Some(list) = current.as_array_mut() else {
+                anyhow::bail!("{} is an index, but the field is not a list", segment);
+            };
+            while list.len() <= index {
+                list.push(Value::Object(Map::new()));
+            }
+            current = &mut list[index];
+        } else {
+            if current.is_null() {
+                *current = Value::Object(Map::new());
+            }
+            let Some(object) = current.as_object_mut() else {
+                anyhow::bail!(
+                    "{} is a field name, but the field is not an object",
+                    segment
+                );
+            };
+            current = object.entry(segment.to_lowercase()).or_insert(Value::Null);
+        }
+    }
+
+    *current = parse_value(raw, is_string_field(path) || current.is_string());
+    Ok(())
+}
+
+fn is_string_field(path: &[&str]) -> bool {
+    match path {
+        [.., list, index] if index.parse::<usize>().is_ok() => {
+            STRING_LISTS.contains(&list.to_lowercase().as_str())
+        }
+        [.., field] => STRING_FIELDS.contains(&field.to_lowercase().as_str()),
+        [] => false,
+    }
+}
+
+/// Text fields, and fields that already hold a string, are set as given. Otherwise the value is
+/// read as JSON if it is valid JSON, so numbers, booleans and whole lists can be set, and as a
+/// string if not.
+fn parse_value(raw: &str, is_string: bool) -> Value {
+    if is_string {
+        return Value::String(raw.to_string());
+    }
+    serde_json::from_str(raw).unwrap_or_else(|_| Value::String(raw.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn vars(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_overrides() {
+        let mut config = json!({
+            "telegram_chat_id": "-999",
+            "polling_interval_seconds": 30,
+            "accounts": [{ "name": "Main", "api_key": "" }]
+        });
+
+        let count = apply_overrides(
+            &mut config,
+            vars(&[
+                ("TRADEBELL_TELEGRAM_CHAT_ID", "-123"),
+                ("TRADEBELL_POLLING_INTERVAL_SECONDS", "60"),
+                ("TRADEBELL_ACCOUNTS__0__API_KEY", "KEY1"),
+                ("TRADEBELL_ACCOUNTS__1__NAME", "Alt"),
+                ("TRADEBELL_PRICING", r#"{ "currency": 3 }"#),
+                ("TRADEBELL_NOTIFIER__TYPE", "discord"),
+                ("HOME", "/root"),
+            ]),
+        )
+        .unwrap();
+
+        assert_eq!(count, 6);
+        assert_eq!(
+            config,
+            json!({
+                "telegram_chat_id": "-123",
+                "polling_interval_seconds": 60,
+                "accounts": [
+                    { "name": "Main", "api_key": "KEY1" },
+                    { "name": "Alt" }
+                ],
+                "pricing": { "currency": 3 },
+                "notifier": { "type": "discord" }
+            })
+        );
+    }
+
+    #[test]
+    fn test_text_fields_stay_text() {
+        let mut config = json!({});
+        apply_overrides(
+            &mut config,
+            vars(&[
+                ("TRADEBELL_TELEGRAM_CHAT_ID", "-1001234567890"),
+                ("TRADEBELL_ACCOUNTS__0__NAME", "42"),
+                (
+                    "TRADEBELL_ACCOUNTS__0__API_KEY",
+                    "12345678901234567890123456789012",
+                ),
+                ("TRADEBELL_RULES__0__PARTNER__0", "76561198000000000"),
+                ("TRADEBELL_RULES__0__MIN_ITEMS", "5"),
+            ]),
+        )
+        .unwrap();
+
+        assert_eq!(
+            config,
+            json!({
+                "telegram_chat_id": "-1001234567890",
+                "accounts": [{ "name": "42", "api_key": "12345678901234567890123456789012" }],
+                "rules": [{ "partner": ["76561198000000000"], "min_items": 5 }]
+            })
+        );
+    }
+
+    #[test]
+    fn test_file_overrides() {
+        let path = std::env::temp_dir().join("test_tradebell_secret");
+        fs::write(&path, "SECRET\n").unwrap();
+        let path = path.to_string_lossy().to_string();
+
+        let mut config = json!({});
+        apply_overrides(
+            &mut config,
+            vars(&[("TRADEBELL_TELEGRAM_TOKEN_FILE", &path)]),
+        )
+        .unwrap();
+        assert_eq!(config, json!({ "telegram_token": "SECRET" }));
+
+        let conflict = apply_overrides(
+            &mut json!({}),
+            vars(&[
+                ("TRADEBELL_TELEGRAM_TOKEN", "A"),
+                ("TRADEBELL_TELEGRAM_TOKEN_FILE", &path),
+            ]),
+        );
+        assert!(conflict.is_err());
+
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_invalid_path() {
+        let mut config = json!({ "polling_interval_seconds": 30 });
+        assert!(
+            apply_overrides(
+                &mut config,
+                vars(&[("TRADEBELL_POLLING_INTERVAL_SECONDS__0", "1")])
+            )
+            .is_err()
+        );
+    }
+}
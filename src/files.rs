@@ -0,0 +1,91 @@
+use anyhow::{Context, Result};
+use std::{
+    fs::{self, File, OpenOptions, TryLockError},
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+/// Name of the lock file in the data directory.
+const LOCK_FILE: &str = "tradebell.lock";
+
+/// Replaces the `what` file at `path` with `content`. The content is written to a temporary
+/// file first and renamed into place, so a crash mid-write can't leave a truncated file behind.
+pub fn write_atomic(path: &Path, content: &str, what: &str) -> Result<()> {
+    let mut temp_path = path.to_path_buf().into_os_string();
+    temp_path.push(".tmp");
+    let temp_path = PathBuf::from(temp_path);
+
+    fs::write(&temp_path, content)
+        .with_context(|| format!("Failed to write {} file: {:?}", what, temp_path))?;
+    fs::rename(&temp_path, path)
+        .with_context(|| format!("Failed to replace {} file: {:?}", what, path))?;
+    Ok(())
+}
+
+/// An exclusive lock on the data directory, so that two processes don't write the same files.
+/// It is released when dropped, or by the OS if the process dies.
+pub struct DataLock {
+    _file: File,
+}
+
+impl DataLock {
+    /// Takes the lock of `data_dir`, creating the directory if it is missing.
+    pub fn for_data_dir(data_dir: &Path) -> Result<Self> {
+        fs::create_dir_all(data_dir)
+            .with_context(|| format!("Failed to create data directory: {:?}", data_dir))?;
+        Self::acquire(&data_dir.join(LOCK_FILE))
+    }
+
+    /// Takes the lock at `path`, failing straight away if another process holds it.
+    fn acquire(path: &Path) -> Result<Self> {
+        let mut file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .read(true)
+            .write(true)
+            .open(path)
+            .with_context(|| format!("Failed to open lock file: {:?}", path))?;
+
+        match file.try_lock() {
+            Ok(()) => {}
+            Err(TryLockError::WouldBlock) => {
+                let pid = fs::read_to_string(path).unwrap_or_default();
+                anyhow::bail!(
+                    "TradeBell is already running with this data directory (pid {}, holding {:?}); \
+                     stop it first.",
+                    pid.trim(),
+                    path
+                );
+            }
+            Err(TryLockError::Error(e)) => {
+                return Err(e).with_context(|| format!("Failed to lock {:?}", path));
+            }
+        }
+
+        // Record who holds the lock, for the message above.
+        file.set_len(0)?;
+        write!(file, "{}", std::process::id())?;
+        Ok(Self { _file: file })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lock_is_exclusive() {
+        let path = std::env::temp_dir().join(format!("test_lock_{}.lock", std::process::id()));
+
+        let lock = DataLock::acquire(&path).expect("Failed to take lock");
+        let error = DataLock::acquire(&path)
+            .err()
+            .expect("Lock was taken twice");
+        assert!(error.to_string().contains("already running"));
+
+        drop(lock);
+        assert!(DataLock::acquire(&path).is_ok());
+
+        let _ = fs::remove_file(&path);
+    }
+}
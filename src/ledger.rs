@@ -0,0 +1,270 @@
+use anyhow::{Context, Result};
+use chrono::Utc;
+use rusqlite::{Connection, params};
+use std::{
+    path::Path,
+    sync::{Arc, Mutex},
+};
+
+use crate::models::{Asset, AssetClassInfo, TradeHistory};
+
+/// Schema migrations, applied in order. The database's `user_version` records how many have run.
+/// Never edit a migration once released; append a new one instead.
+const MIGRATIONS: &[&str] = &[
+    // 1: trades and the assets that moved in them
+    "CREATE TABLE trades (
+        id INTEGER PRIMARY KEY,
+        account TEXT NOT NULL,
+        trade_id TEXT NOT NULL,
+        partner_steamid TEXT NOT NULL,
+        time_init INTEGER NOT NULL,
+        recorded_at INTEGER NOT NULL,
+        UNIQUE (account, trade_id)
+    );
+    CREATE INDEX trades_time_init ON trades (time_init);
+    CREATE TABLE trade_assets (
+        trade_row INTEGER NOT NULL REFERENCES trades (id) ON DELETE CASCADE,
+        direction TEXT NOT NULL CHECK (direction IN ('received', 'given')),
+        appid INTEGER NOT NULL,
+        contextid TEXT NOT NULL,
+        assetid TEXT NOT NULL,
+        classid TEXT NOT NULL,
+        instanceid TEXT NOT NULL,
+        amount TEXT NOT NULL,
+        new_assetid TEXT,
+        new_contextid TEXT,
+        name TEXT,
+        market_hash_name TEXT,
+        market_name TEXT,
+        type TEXT,
+        name_color TEXT,
+        icon_url TEXT
+    );
+    CREATE INDEX trade_assets_trade_row ON trade_assets (trade_row);",
+];
+
+/// A trade as stored in the ledger.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TradeRecord {
+    pub account: String,
+    pub trade_id: String,
+    pub partner_steamid: String,
+    pub time_init: u64,
+    pub items_received: usize,
+    pub items_given: usize,
+}
+
+/// Durable SQLite history of every completed trade.
+#[derive(Clone)]
+pub struct Ledger {
+    conn: Arc<Mutex<Connection>>,
+}
+
+impl Ledger {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let conn = Connection::open(path)
+            .with_context(|| format!("Failed to open trade ledger: {:?}", path))?;
+        Self::from_connection(conn)
+    }
+
+    #[cfg(test)]
+    pub fn open_in_memory() -> Result<Self> {
+        Self::from_connection(Connection::open_in_memory()?)
+    }
+
+    fn from_connection(mut conn: Connection) -> Result<Self> {
+        conn.pragma_update(None, "foreign_keys", "ON")?;
+        migrate(&mut conn)?;
+        Ok(Self {
+            conn: Arc::new(Mutex::new(conn)),
+        })
+    }
+
+    /// Stores a completed trade. `describe` supplies the resolved description of each asset,
+    /// if known. Recording the same trade twice is a no-op.
+    pub fn record_trade<F>(&self, account: &str, trade: &TradeHistory, describe: F) -> Result<()>
+    where
+        F: Fn(&Asset) -> Option<AssetClassInfo>,
+    {
+        let mut conn = self
+            .conn
+            .lock()
+            .map_err(|_| anyhow::anyhow!("Lock poisoned"))?;
+        let tx = conn.transaction()?;
+
+        let inserted = tx.execute(
+            "INSERT OR IGNORE INTO trades (account, trade_id, partner_steamid, time_init, recorded_at)
+             VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![
+                account,
+                trade.tradeid,
+                trade.steamid_other,
+                trade.time_init as i64,
+                Utc::now().timestamp()
+            ],
+        )?;
+        if inserted == 0 {
+            return Ok(());
+        }
+        let trade_row = tx.last_insert_rowid();
+
+        {
+            let mut insert_asset = tx.prepare(
+                "INSERT INTO trade_assets (
+                    trade_row, direction, appid, contextid, assetid, classid, instanceid, amount,
+                    new_assetid, new_contextid, name, market_hash_name, market_name, type,
+                    name_color, icon_url
+                 ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16)",
+            )?;
+
+            let received = trade
+                .assets_received
+                .iter()
+                .flatten()
+                .map(|a| ("received", a));
+            let given = trade.assets_given.iter().flatten().map(|a| ("given", a));
+
+            for (direction, asset) in received.chain(given) {
+                let info = describe(asset);
+                insert_asset.execute(params![
+                    trade_row,
+                    direction,
+                    asset.appid,
+                    asset.contextid,
+                    asset.assetid,
+                    asset.classid,
+                    asset.instanceid,
+                    asset.amount,
+                    asset.new_assetid,
+                    asset.new_contextid,
+                    info.as_ref().map(|i| &i.name),
+                    info.as_ref().map(|i| &i.market_hash_name),
+                    info.as_ref().map(|i| &i.market_name),
+                    info.as_ref().map(|i| &i.type_),
+                    info.as_ref().map(|i| &i.name_color),
+                    info.as_ref().and_then(|i| i.icon_url.as_ref()),
+                ])?;
+            }
+        }
+
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// The most recent trades across all accounts, newest first.
+    pub fn recent_trades(&self, limit: usize) -> Result<Vec<TradeRecord>> {
+        let conn = self
+            .conn
+            .lock()
+            .map_err(|_| anyhow::anyhow!("Lock poisoned"))?;
+        let mut stmt = conn.prepare(
+            "SELECT t.account, t.trade_id, t.partner_steamid, t.time_init,
+                    (SELECT COUNT(*) FROM trade_assets a WHERE a.trade_row = t.id AND a.direction = 'received'),
+                    (SELECT COUNT(*) FROM trade_assets a WHERE a.trade_row = t.id AND a.direction = 'given')
+             FROM trades t
+             ORDER BY t.time_init DESC, t.id DESC
+             LIMIT ?1",
+        )?;
+
+        let rows = stmt.query_map(params![limit as i64], |row| {
+            Ok(TradeRecord {
+                account: row.get(0)?,
+                trade_id: row.get(1)?,
+                partner_steamid: row.get(2)?,
+                time_init: row.get::<_, i64>(3)? as u64,
+                items_received: row.get::<_, i64>(4)? as usize,
+                items_given: row.get::<_, i64>(5)? as usize,
+            })
+        })?;
+
+        Ok(rows.collect::<rusqlite::Result<Vec<_>>>()?)
+    }
+}
+
+fn migrate(conn: &mut Connection) -> Result<()> {
+    let current: i64 = conn.pragma_query_value(None, "user_version", |row| row.get(0))?;
+    let current = current as usize;
+    if current > MIGRATIONS.len() {
+        anyhow::bail!(
+            "Trade ledger schema version {} is newer than this build supports ({}).",
+            current,
+            MIGRATIONS.len()
+        );
+    }
+
+    for (version, migration) in MIGRATIONS.iter().enumerate().skip(current) {
+        let tx = conn.transaction()?;
+        tx.execute_batch(migration)
+            .with_context(|| format!("Failed to apply ledger migration {}", version + 1))?;
+        tx.pragma_update(None, "user_version", (version + 1) as i64)?;
+        tx.commit()?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn asset(assetid: &str) -> Asset {
+        Asset {
+            appid: 440,
+            contextid: "2".to_string(),
+            assetid: assetid.to_string(),
+            classid: "100".to_string(),
+            instanceid: "0".to_string(),
+            amount: "1".to_string(),
+            new_assetid: None,
+            new_contextid: None,
+        }
+    }
+
+    fn trade(tradeid: &str, time_init: u64) -> TradeHistory {
+        TradeHistory {
+            tradeid: tradeid.to_string(),
+            steamid_other: "76561197960287930".to_string(),
+            time_init,
+            assets_received: Some(vec![asset("1"), asset("2")]),
+            assets_given: Some(vec![asset("3")]),
+        }
+    }
+
+    #[test]
+    fn test_record_trades() {
+        let ledger = Ledger::open_in_memory().expect("Failed to open ledger");
+
+        ledger
+            .record_trade("Bot1", &trade("111", 1600000000), |_| None)
+            .expect("Failed to record trade");
+        ledger
+            .record_trade("Bot2", &trade("222", 1600000100), |_| None)
+            .expect("Failed to record trade");
+        // Duplicates are ignored.
+        ledger
+            .record_trade("Bot1", &trade("111", 1600000000), |_| None)
+            .expect("Failed to record trade");
+
+        let recent = ledger.recent_trades(10).expect("Failed to list trades");
+        assert_eq!(recent.len(), 2);
+        assert_eq!(recent[0].trade_id, "222");
+        assert_eq!(recent[1].account, "Bot1");
+        assert_eq!(recent[1].items_received, 2);
+        assert_eq!(recent[1].items_given, 1);
+
+        assert_eq!(ledger.recent_trades(1).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_migrations_are_idempotent() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        migrate(&mut conn).expect("First migration failed");
+        migrate(&mut conn).expect("Second migration failed");
+
+        let version: i64 = conn
+            .pragma_query_value(None, "user_version", |row| row.get(0))
+            .unwrap();
+        assert_eq!(version as usize, MIGRATIONS.len());
+    }
+}
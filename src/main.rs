@@ -1,364 +1,247 @@
 mod cache;
+mod cache_cli;
+mod cli;
+mod commands;
 mod config;
+mod discord;
+mod env;
+mod files;
+mod ledger;
+mod message;
 mod models;
+mod notifier;
+mod outbox;
+mod poller;
+mod pricing;
+mod profiles;
+mod ratelimit;
+mod rules;
+mod scam;
+mod state;
+mod status;
 mod steam;
+mod supervisor;
 mod telegram;
+mod template;
+mod validate;
 
 use anyhow::Result;
-use chrono::Utc;
-use log::{error, info, warn};
-use std::{
-    collections::{BTreeMap, HashMap, HashSet},
-    sync::Arc,
-    time::Duration,
-};
-use tokio::time::sleep;
+use clap::Parser;
+use log::{error, info};
+use std::{sync::Arc, time::Duration};
+use tokio::sync::watch;
 
 use crate::{
-    cache::ItemCache,
-    config::Config,
-    models::{Asset, TradeOffer},
-    steam::SteamClient,
-    telegram::TelegramBot,
+    cli::{Cli, Command},
+    config::{AccountConfig, Config},
+    files::DataLock,
+    models::TradeOfferState,
+    notifier::{TradeEvent, TradeEventKind},
+    rules::RuleSet,
+    supervisor::{Shared, Supervisor, telegram_bot},
 };
 
+/// How long one-shot commands wait for queued Telegram messages to be delivered.
+const FLUSH_TIMEOUT: Duration = Duration::from_secs(60);
+
 #[tokio::main]
 async fn main() -> Result<()> {
-    env_logger::init();
+    let cli = Cli::parse();
 
-    // 1. Load Config
-    let config = Config::load()?;
-    let polling_interval = Duration::from_secs(config.polling_interval_seconds);
+    let mut logger = env_logger::Builder::from_default_env();
+    if let Some(level) = cli.log_level {
+        logger.filter_level(level);
+    }
+    logger.init();
 
-    // 2. Initialize Cache (Shared)
-    let cache = ItemCache::new("cache.json")?;
-    let cache = Arc::new(cache); // thread-safe wrapper inside ItemCache already uses RwLock, but wrapping struct in Arc is good for cloning
+    let config = Config::load(&cli.config)?;
 
-    // 3. Initialize Telegram Bot (Shared)
-    let bot = Arc::new(TelegramBot::new(
-        config.telegram_token.clone(),
-        config.telegram_chat_id.clone(),
-    ));
+    match cli.command.clone().unwrap_or(Command::Run) {
+        Command::Run => run(&cli, config).await,
+        Command::CheckConfig => check_config(&cli, &config).await,
+        Command::TestNotify { account } => test_notify(&cli, &config, account.as_deref()).await,
+        Command::Backfill { since, account } => {
+            backfill(&cli, config, since, account.as_deref()).await
+        }
+        Command::Cache { command } => cache_cli::run(&cli, &config, command).await,
+    }
+}
+
+/// Watches every account until the process is stopped, reloading the config when it changes.
+async fn run(cli: &Cli, config: Config) -> Result<()> {
+    // 1. Initialize Cache, Poller State, Trade Ledger and Telegram Bot (Shared)
+    let _lock = DataLock::for_data_dir(&cli.data_dir)?;
+    let shared = Shared::new(cli, &config)?;
+    if cli.probe {
+        validate::probe(&config, shared.telegram.as_deref()).await?;
+    }
+    if let Some(bot) = &shared.telegram {
+        tokio::spawn(bot.clone().run_sender());
+    }
+
+    // 2. Start Telegram Bot Commands
+    if config.telegram_commands {
+        let Some(bot) = shared
+            .telegram
+            .clone()
+            .filter(|_| !config.telegram_chat_id.is_empty())
+        else {
+            anyhow::bail!(
+                "telegram_commands is enabled but telegram_token or telegram_chat_id is missing."
+            );
+        };
+        tokio::spawn(commands::run(
+            bot,
+            shared.status.clone(),
+            shared.ledger.clone(),
+            cli.data_path("telegram_offset"),
+        ));
+    }
 
     info!(
         "Starting Steam Trade Watcher with {} accounts...",
         config.accounts.len()
     );
 
-    // 4. Spawn Tasks
-    let mut handles = vec![];
-
-    for account in config.accounts {
-        let cache_clone = cache.clone();
-        let bot_clone = bot.clone();
-        let client = SteamClient::new(account.api_key.clone());
-        let account_name = account.name.clone();
-
-        let handle = tokio::spawn(async move {
-            info!("[{}] Poller started.", account_name);
-
-            // Track processed trade IDs to prevent duplicates within this session.
-            let mut processed_trades: HashSet<String> = HashSet::new();
-
-            // Ignore trades processed before program startup.
-            let boot_time = Utc::now().timestamp() as u64;
-            let mut last_poll_time = boot_time;
-
-            loop {
-                // Poll
-                match client.get_active_trade_offers(last_poll_time).await {
-                    Ok(offers) => {
-                        let mut new_trades = Vec::new();
-
-                        // Check received offers
-                        for offer in offers.response.trade_offers_received {
-                            if offer.trade_offer_state == 3
-                                && !processed_trades.contains(&offer.tradeofferid)
-                            {
-                                if offer.time_updated < boot_time {
-                                    continue;
-                                }
-                                new_trades.push(offer);
-                            }
-                        }
-
-                        // Also check sent offers (if we care about completed sent trades? usually yes)
-                        for offer in offers.response.trade_offers_sent {
-                            if offer.trade_offer_state == 3
-                                && !processed_trades.contains(&offer.tradeofferid)
-                            {
-                                // Ignore trades made before program startup
-                                if offer.time_updated < boot_time {
-                                    continue;
-                                }
-                                new_trades.push(offer);
-                            }
-                        }
-
-                        // Process new trades
-                        for trade in new_trades {
-                            info!(
-                                "[{}] Found new completed trade: {}",
-                                account_name, trade.tradeofferid
-                            );
-                            processed_trades.insert(trade.tradeofferid.clone());
-
-                            // Fetch History to get items
-                            match process_completed_trade(&client, &cache_clone, &trade).await {
-                                Ok(Some(notification_msg)) => {
-                                    let full_msg = format!(
-                                        "<b>Account: {}</b>\n{}",
-                                        account_name, notification_msg
-                                    );
-                                    if let Err(e) = bot_clone.send_notification(&full_msg).await {
-                                        error!(
-                                            "[{}] Failed to send notification: {}",
-                                            account_name, e
-                                        );
-                                    }
-                                }
-                                Ok(None) => {
-                                    warn!(
-                                        "[{}] Trade history not found for {}, skipping notification.",
-                                        account_name, trade.tradeofferid
-                                    );
-                                }
-                                Err(e) => error!(
-                                    "[{}] Failed to process trade details: {}",
-                                    account_name, e
-                                ),
-                            }
-                        }
-
-                        // Update cutoff timestamp.
-                        last_poll_time = Utc::now().timestamp() as u64 - 60;
-                    }
-                    Err(e) => {
-                        error!("[{}] Metadata poll failed: {}", account_name, e);
-                    }
-                }
-
-                sleep(polling_interval).await;
-            }
-        });
-        handles.push(handle);
-    }
+    // 3. Spawn Pollers, then follow changes to the config file until stopped
+    let cache = shared.cache.clone();
+    tokio::spawn(cache.clone().run_flusher());
+    // Stale item descriptions are refreshed with the first account's API key.
+    tokio::spawn(
+        cache
+            .clone()
+            .run_refresher(shared.refresh_key.subscribe(), shared.steam_limiter.clone()),
+    );
 
-    // Wait forever
-    for h in handles {
-        let _ = h.await;
+    let mut supervisor = Supervisor::new(shared);
+    supervisor.apply(config).await?;
+    tokio::select! {
+        _ = supervisor.watch_config(cli.config.clone()) => {}
+        _ = shutdown_signal() => info!("Shutting down..."),
     }
 
-    Ok(())
+    cache.flush()
 }
 
-async fn process_completed_trade(
-    client: &SteamClient,
-    cache: &ItemCache,
-    trade: &TradeOffer,
-) -> Result<Option<String>> {
-    // 1. Get History
-    // We search near the trade update time.
-    let history_response = client.get_trade_history(trade.time_updated - 60).await?;
-
-    // Identify the trade history entry closest to the `time_updated` of the trade offer.
-    // This heuristic associates the trade offer with its history since `tradeofferid` is not always present in history.
-
-    let mut best_match: Option<crate::models::TradeHistory> = None;
-    for hist in history_response.response.trades {
-        // time_init is when trade started/completed.
-        let time_diff = (hist.time_init as i64 - trade.time_updated as i64).abs();
-        if time_diff < 120 {
-            // within 2 minutes
-            best_match = Some(hist);
-            break; // Assume first (newest) one is correct? API returns newest first.
-        }
-    }
-
-    let hist = match best_match {
-        Some(h) => h,
-        None => return Ok(None),
-    };
-
-    let mut message_lines = Vec::new();
-    message_lines.push(format!("Trade ID: {}", hist.tradeid));
-
-    // Process Received
-    if let Some(assets) = hist.assets_received
-        && !assets.is_empty()
+/// Completes on Ctrl+C, or SIGTERM as sent by `docker stop`.
+async fn shutdown_signal() {
+    #[cfg(unix)]
     {
-        message_lines.push("\n<b>Received:</b>".to_string());
-        let names = resolve_asset_names(client, cache, &assets).await?;
-        for line in group_and_format_items(names) {
-            message_lines.push(format!("- {}", line));
+        use tokio::signal::unix::{SignalKind, signal};
+        if let Ok(mut terminate) = signal(SignalKind::terminate()) {
+            tokio::select! {
+                _ = tokio::signal::ctrl_c() => {}
+                _ = terminate.recv() => {}
+            }
+            return;
         }
     }
+    let _ = tokio::signal::ctrl_c().await;
+}
 
-    // Process Given
-    if let Some(assets) = hist.assets_given
-        && !assets.is_empty()
-    {
-        message_lines.push("\n<b>Given:</b>".to_string());
-        let names = resolve_asset_names(client, cache, &assets).await?;
-        for line in group_and_format_items(names) {
-            message_lines.push(format!("- {}", line));
-        }
+/// Loads everything `run` would, without touching the network unless `--probe` is given.
+async fn check_config(cli: &Cli, config: &Config) -> Result<()> {
+    RuleSet::new(&config.rules)?;
+    let telegram = telegram_bot(cli, config)?;
+    for account in &config.accounts {
+        notifier::for_account(account, config, telegram.as_ref())?;
+    }
+    if cli.probe {
+        validate::probe(config, telegram.as_deref()).await?;
     }
 
-    Ok(Some(message_lines.join("\n")))
+    println!(
+        "{} is valid: {} accounts, {} rules.",
+        cli.config.display(),
+        config.accounts.len(),
+        config.rules.len()
+    );
+    Ok(())
 }
 
-async fn resolve_asset_names(
-    client: &SteamClient,
-    cache: &ItemCache,
-    assets: &[Asset],
-) -> Result<Vec<String>> {
-    let mut names = Vec::new();
-    let mut to_fetch: Vec<(String, String)> = Vec::new();
-    let mut asset_map: HashMap<(String, String), String> = HashMap::new(); // (classid, instanceid) -> Name
-
-    // 1. Check Cache
-    for asset in assets {
-        if let Some(info) = cache.get(&asset.classid, &asset.instanceid) {
-            asset_map.insert(
-                (asset.classid.clone(), asset.instanceid.clone()),
-                info.market_hash_name,
-            );
-        } else {
-            to_fetch.push((asset.classid.clone(), asset.instanceid.clone()));
-        }
+/// Sends a sample notification through each selected account's destinations.
+async fn test_notify(cli: &Cli, config: &Config, account: Option<&str>) -> Result<()> {
+    // The Telegram outbox is shared with `run`, which would deliver its messages twice.
+    let _lock = DataLock::for_data_dir(&cli.data_dir)?;
+    let telegram = telegram_bot(cli, config)?;
+    if let Some(bot) = &telegram {
+        tokio::spawn(bot.clone().run_sender());
     }
 
-    // 2. Fetch missing
-    if !to_fetch.is_empty() {
-        // Unique pairs only
-        to_fetch.sort();
-        to_fetch.dedup();
-
-        // Group assets by AppID to batch API requests.
-        let mut by_appid: HashMap<u32, Vec<(String, String)>> = HashMap::new();
-        for asset in assets {
-            // Find if this specific pair needs fetching
-            if !asset_map.contains_key(&(asset.classid.clone(), asset.instanceid.clone())) {
-                by_appid
-                    .entry(asset.appid)
-                    .or_default()
-                    .push((asset.classid.clone(), asset.instanceid.clone()));
-            }
-        }
-
-        for (appid, pairs) in by_appid {
-            if pairs.is_empty() {
-                continue;
-            }
-            // Dedup again just in case
-            let mut unique_pairs = pairs.clone();
-            unique_pairs.sort();
-            unique_pairs.dedup();
-
-            match client.get_asset_class_info(appid, &unique_pairs).await {
-                Ok(info_map) => {
-                    for (cid, iid) in unique_pairs {
-                        // Try key = cid
-                        if let Some(info) = info_map.get(&cid) {
-                            cache.insert(&cid, &iid, info.clone()).unwrap_or_default();
-                            asset_map
-                                .insert((cid.clone(), iid.clone()), info.market_hash_name.clone());
-                        }
-                        // Fallback: check composite key format
-                        else if let Some(info) = info_map.get(&format!("{}_{}", cid, iid)) {
-                            cache.insert(&cid, &iid, info.clone()).unwrap_or_default();
-                            asset_map
-                                .insert((cid.clone(), iid.clone()), info.market_hash_name.clone());
-                        }
-                    }
-                }
-                Err(e) => error!("Failed to enrich items for app {}: {}", appid, e),
+    let mut failed = false;
+    for account in select_accounts(config, account)? {
+        let notifier = notifier::for_account(account, config, telegram.as_ref())?;
+
+        let mut event = TradeEvent::new(
+            &account.name,
+            TradeEventKind::Completed,
+            "0",
+            TradeOfferState::Accepted,
+        );
+        event.message = Some("This is a test notification from TradeBell.".to_string());
+        event.items_received = vec!["Test Item".to_string()];
+
+        match notifier.send(&event).await {
+            Ok(()) => info!("[{}] Test notification sent.", account.name),
+            Err(e) => {
+                error!("[{}] Failed to send test notification: {}", account.name, e);
+                failed = true;
             }
         }
     }
 
-    // 3. Construct names list
-    for asset in assets {
-        if let Some(name) = asset_map.get(&(asset.classid.clone(), asset.instanceid.clone())) {
-            names.push(name.clone());
-        } else {
-            names.push(format!(
-                "Unknown Asset ({})",
-                asset.market_name_or_fallback()
-            ));
-        }
-    }
-
-    Ok(names)
-}
-
-trait AssetFallback {
-    fn market_name_or_fallback(&self) -> String;
-}
-
-impl AssetFallback for Asset {
-    fn market_name_or_fallback(&self) -> String {
-        // We don't have the name in the Asset struct itself from history?
-        // History `assets_received` often just has IDs.
-        format!("ID: {}", self.assetid)
+    if let Some(bot) = &telegram
+        && !bot.flush(FLUSH_TIMEOUT).await
+    {
+        anyhow::bail!("Timed out delivering Telegram messages; they remain queued in the outbox.");
     }
-}
-
-fn group_and_format_items(items: Vec<String>) -> Vec<String> {
-    let mut counts = BTreeMap::new();
-    for item in items {
-        *counts.entry(item).or_insert(0) += 1;
+    if failed {
+        anyhow::bail!("Some test notifications could not be sent.");
     }
-
-    counts
-        .into_iter()
-        .map(|(name, count)| {
-            if count > 1 {
-                format!("{}x {}", count, name)
-            } else {
-                name
-            }
-        })
-        .collect()
+    Ok(())
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-
-    #[test]
-    fn test_group_items_single() {
-        let items = vec!["Key".to_string()];
-        let result = group_and_format_items(items);
-        assert_eq!(result, vec!["Key"]);
+/// Notifies about trades since `since` that were never notified, for the selected accounts.
+async fn backfill(cli: &Cli, config: Config, since: u64, account: Option<&str>) -> Result<()> {
+    // State, cache and outbox are all written, so `run` must not be using them.
+    let _lock = DataLock::for_data_dir(&cli.data_dir)?;
+    let shared = Shared::new(cli, &config)?;
+    if let Some(bot) = &shared.telegram {
+        tokio::spawn(bot.clone().run_sender());
     }
 
-    #[test]
-    fn test_group_items_multiple_distinct() {
-        let items = vec!["Key".to_string(), "Crate".to_string()];
-        // BTreeMap sorts keys, so Crate comes before Key
-        let result = group_and_format_items(items);
-        assert_eq!(result, vec!["Crate", "Key"]);
+    let rules = RuleSet::new(&config.rules)?;
+    for account in select_accounts(&config, account)? {
+        info!("[{}] Backfilling trades since {}...", account.name, since);
+        let settings = shared.settings(account, &config, &rules)?;
+        let (_, settings) = watch::channel(Arc::new(settings));
+        let (_, stop) = watch::channel(false);
+        shared
+            .poller(account, settings, stop)
+            .backfill(since)
+            .await?;
     }
+    shared.cache.flush()?;
 
-    #[test]
-    fn test_group_items_duplicates() {
-        let items = vec!["Key".to_string(), "Key".to_string(), "Key".to_string()];
-        let result = group_and_format_items(items);
-        assert_eq!(result, vec!["3x Key"]);
+    if let Some(bot) = &shared.telegram
+        && !bot.flush(FLUSH_TIMEOUT).await
+    {
+        anyhow::bail!("Timed out delivering Telegram messages; they remain queued in the outbox.");
     }
+    Ok(())
+}
 
-    #[test]
-    fn test_group_items_mixed() {
-        let items = vec![
-            "Key".to_string(),
-            "Crate".to_string(),
-            "Key".to_string(),
-            "Hat".to_string(),
-        ];
-        // Sorted: Crate, Hat, Key (2)
-        let result = group_and_format_items(items);
-        assert_eq!(result, vec!["Crate", "Hat", "2x Key"]);
+/// All accounts, or only the one named (case-insensitively).
+fn select_accounts<'a>(config: &'a Config, name: Option<&str>) -> Result<Vec<&'a AccountConfig>> {
+    let accounts: Vec<_> = config
+        .accounts
+        .iter()
+        .filter(|a| name.is_none_or(|name| a.name.eq_ignore_ascii_case(name)))
+        .collect();
+    if let Some(name) = name
+        && accounts.is_empty()
+    {
+        anyhow::bail!("No account named {} in the config.", name);
     }
+    Ok(accounts)
 }
@@ -0,0 +1,140 @@
+/// A piece of a line of notification text. Text inside a span is always plain and untrusted;
+/// it is escaped when the line is rendered.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Span {
+    Text(String),
+    Bold(String),
+    Link { text: String, url: String },
+}
+
+/// One line of a notification, built from spans and rendered for a specific backend.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Line(pub Vec<Span>);
+
+impl Line {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn text(mut self, text: impl Into<String>) -> Self {
+        self.0.push(Span::Text(text.into()));
+        self
+    }
+
+    pub fn bold(mut self, text: impl Into<String>) -> Self {
+        self.0.push(Span::Bold(text.into()));
+        self
+    }
+
+    pub fn link(mut self, text: impl Into<String>, url: impl Into<String>) -> Self {
+        self.0.push(Span::Link {
+            text: text.into(),
+            url: url.into(),
+        });
+        self
+    }
+
+    /// Renders the line for Telegram's `parse_mode: HTML`.
+    pub fn to_html(&self) -> String {
+        self.0
+            .iter()
+            .map(|span| match span {
+                Span::Text(text) => escape_html(text),
+                Span::Bold(text) => format!("<b>{}</b>", escape_html(text)),
+                Span::Link { text, url } => format!(
+                    "<a href=\"{}\">{}</a>",
+                    escape_html(url).replace('"', "&quot;"),
+                    escape_html(text)
+                ),
+            })
+            .collect()
+    }
+
+    /// Renders the line as Discord markdown.
+    pub fn to_markdown(&self) -> String {
+        self.0
+            .iter()
+            .map(|span| match span {
+                Span::Text(text) => escape_markdown(text),
+                Span::Bold(text) => format!("**{}**", escape_markdown(text)),
+                // The angle brackets stop Discord from embedding a preview of the page.
+                Span::Link { text, url } => format!("[{}](<{}>)", escape_markdown(text), url),
+            })
+            .collect()
+    }
+}
+
+/// A notification: a header that identifies it, followed by the body lines.
+/// Empty lines separate sections.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Message {
+    pub header: Line,
+    pub body: Vec<Line>,
+}
+
+impl Message {
+    pub fn body_html(&self) -> String {
+        render(&self.body, Line::to_html)
+    }
+
+    pub fn body_markdown(&self) -> String {
+        render(&self.body, Line::to_markdown)
+    }
+}
+
+fn render(lines: &[Line], f: fn(&Line) -> String) -> String {
+    lines.iter().map(f).collect::<Vec<_>>().join("\n")
+}
+
+/// Escapes text for use in a message sent with `parse_mode: HTML`.
+pub fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+/// Escapes characters Discord would treat as markdown.
+pub fn escape_markdown(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if matches!(
+            c,
+            '\\' | '*' | '_' | '~' | '`' | '|' | '>' | '<' | '#' | '[' | ']' | '(' | ')'
+        ) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_escape_html() {
+        assert_eq!(
+            escape_html("<b>Tom & Jerry's</b>"),
+            "&lt;b&gt;Tom &amp; Jerry's&lt;/b&gt;"
+        );
+    }
+
+    #[test]
+    fn test_untrusted_text_is_escaped() {
+        let line = Line::new()
+            .text("Partner: ")
+            .link("<script> & *co*", "https://example.com/?a=1&b=\"2\"")
+            .text(" said ")
+            .bold("__hi__ <3");
+
+        assert_eq!(
+            line.to_html(),
+            "Partner: <a href=\"https://example.com/?a=1&amp;b=&quot;2&quot;\">&lt;script&gt; &amp; *co*</a> said <b>__hi__ &lt;3</b>"
+        );
+        assert_eq!(
+            line.to_markdown(),
+            "Partner: [\\<script\\> & \\*co\\*](<https://example.com/?a=1&b=\"2\">) said **\\_\\_hi\\_\\_ \\<3**"
+        );
+    }
+}
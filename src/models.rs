@@ -1,4 +1,12 @@
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize};
+use std::fmt;
+
+/// Offset between a 32-bit Steam account ID and its individual-account SteamID64.
+const STEAMID64_BASE: u64 = 76561197960265728;
+
+pub fn account_id_to_steamid64(accountid: i64) -> u64 {
+    STEAMID64_BASE + accountid as u64
+}
 
 // --- GetTradeOffers ---
 
@@ -15,14 +23,150 @@ pub struct TradeOffersResponseData {
     pub trade_offers_sent: Vec<TradeOffer>,
 }
 
+/// Steam's `ETradeOfferState`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+#[serde(into = "i32")]
+pub enum TradeOfferState {
+    Invalid,
+    Active,
+    Accepted,
+    Countered,
+    Expired,
+    Canceled,
+    Declined,
+    InvalidItems,
+    CreatedNeedsConfirmation,
+    CanceledBySecondFactor,
+    InEscrow,
+    Unknown(i32),
+}
+
+impl TradeOfferState {
+    const NAMED: [TradeOfferState; 11] = [
+        Self::Invalid,
+        Self::Active,
+        Self::Accepted,
+        Self::Countered,
+        Self::Expired,
+        Self::Canceled,
+        Self::Declined,
+        Self::InvalidItems,
+        Self::CreatedNeedsConfirmation,
+        Self::CanceledBySecondFactor,
+        Self::InEscrow,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Invalid => "Invalid",
+            Self::Active => "Active",
+            Self::Accepted => "Accepted",
+            Self::Countered => "Countered",
+            Self::Expired => "Expired",
+            Self::Canceled => "Canceled",
+            Self::Declined => "Declined",
+            Self::InvalidItems => "InvalidItems",
+            Self::CreatedNeedsConfirmation => "CreatedNeedsConfirmation",
+            Self::CanceledBySecondFactor => "CanceledBySecondFactor",
+            Self::InEscrow => "InEscrow",
+            Self::Unknown(_) => "Unknown",
+        }
+    }
+
+    /// Whether the offer can still change state: it awaits an answer, a confirmation or the
+    /// end of an escrow hold.
+    pub fn is_open(&self) -> bool {
+        matches!(
+            self,
+            Self::Active | Self::CreatedNeedsConfirmation | Self::InEscrow
+        )
+    }
+}
+
+impl From<i32> for TradeOfferState {
+    fn from(value: i32) -> Self {
+        match value {
+            1 => Self::Invalid,
+            2 => Self::Active,
+            3 => Self::Accepted,
+            4 => Self::Countered,
+            5 => Self::Expired,
+            6 => Self::Canceled,
+            7 => Self::Declined,
+            8 => Self::InvalidItems,
+            9 => Self::CreatedNeedsConfirmation,
+            10 => Self::CanceledBySecondFactor,
+            11 => Self::InEscrow,
+            other => Self::Unknown(other),
+        }
+    }
+}
+
+impl From<TradeOfferState> for i32 {
+    fn from(state: TradeOfferState) -> Self {
+        match state {
+            TradeOfferState::Invalid => 1,
+            TradeOfferState::Active => 2,
+            TradeOfferState::Accepted => 3,
+            TradeOfferState::Countered => 4,
+            TradeOfferState::Expired => 5,
+            TradeOfferState::Canceled => 6,
+            TradeOfferState::Declined => 7,
+            TradeOfferState::InvalidItems => 8,
+            TradeOfferState::CreatedNeedsConfirmation => 9,
+            TradeOfferState::CanceledBySecondFactor => 10,
+            TradeOfferState::InEscrow => 11,
+            TradeOfferState::Unknown(other) => other,
+        }
+    }
+}
+
+impl fmt::Display for TradeOfferState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Unknown(value) => write!(f, "Unknown ({})", value),
+            other => f.write_str(other.name()),
+        }
+    }
+}
+
+// Steam sends the numeric value; config files may also use the variant name.
+impl<'de> Deserialize<'de> for TradeOfferState {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Raw {
+            Number(i32),
+            Name(String),
+        }
+
+        match Raw::deserialize(deserializer)? {
+            Raw::Number(value) => Ok(Self::from(value)),
+            Raw::Name(name) => Self::NAMED
+                .into_iter()
+                .find(|state| state.name().eq_ignore_ascii_case(&name))
+                .ok_or_else(|| {
+                    serde::de::Error::custom(format!("unknown trade offer state: {}", name))
+                }),
+        }
+    }
+}
+
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct TradeOffer {
     pub tradeofferid: String,
-    pub trade_offer_state: i32,
+    pub trade_offer_state: TradeOfferState,
     pub message: Option<String>,
     pub time_created: u64,
     pub time_updated: u64,
     pub accountid_other: i64,
+    /// Trade history ID, present once the offer has been accepted.
+    #[serde(default)]
+    pub tradeid: Option<String>,
+    #[serde(default)]
+    pub items_to_give: Option<Vec<Asset>>,
+    #[serde(default)]
+    pub items_to_receive: Option<Vec<Asset>>,
 }
 
 // --- GetTradeHistory ---
@@ -75,6 +219,69 @@ pub struct AssetClassInfo {
     pub type_: String,
 }
 
+// --- Community Inventory ---
+
+#[derive(Debug, Deserialize)]
+pub struct GetInventoryResponse {
+    #[serde(default)]
+    pub descriptions: Vec<InventoryDescription>,
+    /// 1 when there is another page, starting after `last_assetid`.
+    #[serde(default)]
+    pub more_items: Option<u8>,
+    pub last_assetid: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct InventoryDescription {
+    pub appid: u32,
+    pub classid: String,
+    pub instanceid: String,
+    pub icon_url: Option<String>,
+    #[serde(default)]
+    pub name: String,
+    #[serde(default)]
+    pub market_hash_name: String,
+    #[serde(default)]
+    pub market_name: String,
+    #[serde(default)]
+    pub name_color: String,
+    #[serde(default, rename = "type")]
+    pub type_: String,
+}
+
+impl From<InventoryDescription> for AssetClassInfo {
+    fn from(description: InventoryDescription) -> Self {
+        Self {
+            icon_url: description.icon_url,
+            name: description.name,
+            market_hash_name: description.market_hash_name,
+            market_name: description.market_name,
+            name_color: description.name_color,
+            type_: description.type_,
+        }
+    }
+}
+
+// --- GetPlayerSummaries ---
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct GetPlayerSummariesResponse {
+    pub response: PlayerSummariesResponseData,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct PlayerSummariesResponseData {
+    #[serde(default)]
+    pub players: Vec<PlayerSummary>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct PlayerSummary {
+    pub steamid: String,
+    pub personaname: String,
+    pub avatarfull: Option<String>,
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -95,10 +302,76 @@ mod tests {
             serde_json::from_value(json_data).expect("Failed to parse TradeOffer");
 
         assert_eq!(offer.tradeofferid, "123456");
-        assert_eq!(offer.trade_offer_state, 3);
+        assert_eq!(offer.trade_offer_state, TradeOfferState::Accepted);
         assert_eq!(offer.message, Some("Hello".to_string()));
         assert_eq!(offer.time_created, 1600000000);
         assert_eq!(offer.accountid_other, 987654321);
+        assert!(offer.items_to_give.is_none());
+        assert!(offer.tradeid.is_none());
+    }
+
+    #[test]
+    fn test_accepted_trade_offer_tradeid() {
+        let json_data = json!({
+            "tradeofferid": "123456",
+            "tradeid": "3622543526924228084",
+            "trade_offer_state": 3,
+            "time_created": 1600000000,
+            "time_updated": 1600000060,
+            "accountid_other": 987654321
+        });
+
+        let offer: TradeOffer =
+            serde_json::from_value(json_data).expect("Failed to parse TradeOffer");
+
+        assert_eq!(offer.tradeid.as_deref(), Some("3622543526924228084"));
+    }
+
+    #[test]
+    fn test_trade_offer_with_items_deserialization() {
+        let json_data = json!({
+            "tradeofferid": "123456",
+            "trade_offer_state": 2,
+            "time_created": 1600000000,
+            "time_updated": 1600000000,
+            "accountid_other": 987654321,
+            "items_to_receive": [{
+                "appid": 440,
+                "contextid": "2",
+                "assetid": "5000",
+                "classid": "100",
+                "instanceid": "0",
+                "amount": "1",
+                "missing": false
+            }]
+        });
+
+        let offer: TradeOffer =
+            serde_json::from_value(json_data).expect("Failed to parse TradeOffer");
+
+        assert!(offer.message.is_none());
+        assert!(offer.items_to_give.is_none());
+        assert_eq!(offer.items_to_receive.unwrap()[0].assetid, "5000");
+    }
+
+    #[test]
+    fn test_trade_offer_state_from_number_and_name() {
+        let escrow: TradeOfferState = serde_json::from_value(json!(11)).unwrap();
+        assert_eq!(escrow, TradeOfferState::InEscrow);
+
+        let declined: TradeOfferState = serde_json::from_value(json!("declined")).unwrap();
+        assert_eq!(declined, TradeOfferState::Declined);
+
+        let unknown: TradeOfferState = serde_json::from_value(json!(42)).unwrap();
+        assert_eq!(unknown, TradeOfferState::Unknown(42));
+        assert_eq!(serde_json::to_value(unknown).unwrap(), json!(42));
+
+        assert!(serde_json::from_value::<TradeOfferState>(json!("Bogus")).is_err());
+    }
+
+    #[test]
+    fn test_account_id_to_steamid64() {
+        assert_eq!(account_id_to_steamid64(22202), 76561197960287930);
     }
 
     #[test]
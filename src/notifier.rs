@@ -0,0 +1,437 @@
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use std::sync::Arc;
+
+use crate::{
+    config::{AccountConfig, Config, NotifierConfig},
+    discord::DiscordWebhook,
+    message::{Line, Message},
+    models::TradeOfferState,
+    pricing::Valuation,
+    profiles::Partner,
+    telegram::{ChatTarget, TelegramBot, TelegramNotifier},
+    template::{Markup, Template},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TradeEventKind {
+    /// A trade went through; `trade_id` is the trade history ID.
+    Completed,
+    /// A new offer awaiting our response; `trade_id` is the trade offer ID.
+    IncomingOffer,
+    /// An offer moved to a state configured in `state_notifications`;
+    /// `trade_id` is the trade offer ID.
+    OfferStateChanged,
+    /// A sent offer was canceled and re-sent with the same items to someone else;
+    /// `trade_id` is the replacement trade offer ID.
+    SuspectedRedirect,
+}
+
+impl TradeEventKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Completed => "completed",
+            Self::IncomingOffer => "incoming_offer",
+            Self::OfferStateChanged => "offer_state_changed",
+            Self::SuspectedRedirect => "suspected_redirect",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Priority {
+    #[default]
+    Normal,
+    /// Needs immediate attention; backends should make it as loud as they can.
+    High,
+}
+
+/// The canceled offer that a suspected redirect replaced.
+#[derive(Debug, Clone)]
+pub struct ReplacedOffer {
+    pub tradeofferid: String,
+    /// The partner the original offer was sent to.
+    pub partner: Partner,
+}
+
+/// An item in a trade, with as much of its description as is known.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EventItem {
+    pub appid: u32,
+    pub market_hash_name: Option<String>,
+    pub type_: Option<String>,
+    /// Whether the watched account receives the item, rather than gives it.
+    pub received: bool,
+}
+
+/// A trade or trade offer, ready to be rendered by a notification backend.
+/// Item lists are always from the watched account's point of view.
+#[derive(Debug, Clone)]
+pub struct TradeEvent {
+    pub account: String,
+    pub kind: TradeEventKind,
+    pub trade_id: String,
+    /// The other party, when known.
+    pub partner: Option<Partner>,
+    /// Message attached to the trade offer, if any.
+    pub message: Option<String>,
+    pub state: TradeOfferState,
+    /// State the offer was last seen in, `None` if it was first seen in `state`.
+    pub previous_state: Option<TradeOfferState>,
+    /// Grouped item lines, e.g. "2x Mann Co. Supply Crate Key".
+    pub items_received: Vec<String>,
+    pub items_given: Vec<String>,
+    /// Every item on both sides, individually.
+    pub items: Vec<EventItem>,
+    pub priority: Priority,
+    pub replaced_offer: Option<ReplacedOffer>,
+    /// Market value of the items, when pricing is enabled.
+    pub valuation: Option<Valuation>,
+}
+
+impl TradeEvent {
+    pub fn new(
+        account: &str,
+        kind: TradeEventKind,
+        trade_id: &str,
+        state: TradeOfferState,
+    ) -> Self {
+        Self {
+            account: account.to_string(),
+            kind,
+            trade_id: trade_id.to_string(),
+            partner: None,
+            message: None,
+            state,
+            previous_state: None,
+            items_received: Vec::new(),
+            items_given: Vec::new(),
+            items: Vec::new(),
+            priority: Priority::Normal,
+            replaced_offer: None,
+            valuation: None,
+        }
+    }
+
+    /// A warning to show above everything else, if the event calls for one.
+    pub fn alert_line(&self) -> Option<String> {
+        let replaced = self.replaced_offer.as_ref()?;
+        Some(format!(
+            "Possible trade redirect: sent offer {} to {} was canceled and re-sent with the same items to a different account. \
+             Your Steam API key may be compromised; cancel this offer and revoke the key.",
+            replaced.tradeofferid,
+            replaced.partner.display_name()
+        ))
+    }
+
+    /// Headings for the ID line and the received/given item sections.
+    pub fn labels(&self) -> (&'static str, &'static str, &'static str) {
+        match self.kind {
+            TradeEventKind::Completed => ("Trade ID", "Received:", "Given:"),
+            TradeEventKind::IncomingOffer => (
+                "New incoming offer",
+                "You would receive:",
+                "You would give:",
+            ),
+            TradeEventKind::OfferStateChanged => {
+                ("Trade offer", "Offered to receive:", "Offered to give:")
+            }
+            TradeEventKind::SuspectedRedirect => {
+                ("Suspicious trade offer", "Would receive:", "Would give:")
+            }
+        }
+    }
+
+    /// Describes how the offer got to its current state, e.g. "Active → Declined".
+    pub fn state_line(&self) -> Option<String> {
+        match (self.kind, self.previous_state) {
+            (TradeEventKind::OfferStateChanged, Some(previous)) => {
+                Some(format!("State: {} → {}", previous, self.state))
+            }
+            (TradeEventKind::OfferStateChanged, None) => Some(format!("State: {}", self.state)),
+            (TradeEventKind::Completed, Some(TradeOfferState::InEscrow)) => {
+                Some("State: Released from escrow".to_string())
+            }
+            _ => None,
+        }
+    }
+
+    /// Lays out the notification independently of any backend's markup.
+    pub fn to_message(&self) -> Message {
+        let (id_label, received_label, given_label) = self.labels();
+        let header = Line::new().bold(format!("Account: {}", self.account));
+
+        let mut body = Vec::new();
+        if let Some(alert) = self.alert_line() {
+            body.push(Line::new().text("🚨 ").bold(alert));
+        }
+        body.push(Line::new().text(format!("{}: {}", id_label, self.trade_id)));
+
+        if let Some(state) = self.state_line() {
+            body.push(Line::new().text(state));
+        }
+        if let Some(partner) = &self.partner {
+            body.push(
+                Line::new()
+                    .text("Partner: ")
+                    .link(partner.display_name(), partner.profile_url()),
+            );
+        }
+        if let Some(message) = &self.message {
+            body.push(Line::new().text(format!("Message: {}", message)));
+        }
+
+        for (label, items) in [
+            (received_label, &self.items_received),
+            (given_label, &self.items_given),
+        ] {
+            if !items.is_empty() {
+                body.push(Line::new());
+                body.push(Line::new().bold(label));
+                body.extend(items.iter().map(|i| Line::new().text(format!("- {}", i))));
+            }
+        }
+
+        if let Some(valuation) = &self.valuation {
+            body.push(Line::new());
+            body.push(Line::new().bold("Value:"));
+            body.extend(
+                valuation
+                    .item_lines()
+                    .into_iter()
+                    .map(|l| Line::new().text(l)),
+            );
+            body.push(Line::new().text(valuation.summary_line()));
+        }
+
+        Message { header, body }
+    }
+}
+
+#[async_trait]
+pub trait Notifier: Send + Sync {
+    async fn send(&self, event: &TradeEvent) -> Result<()>;
+}
+
+/// Sends every event to several destinations.
+pub struct Fanout(Vec<Arc<dyn Notifier>>);
+
+#[async_trait]
+impl Notifier for Fanout {
+    /// Tries every destination, failing if any of them failed.
+    async fn send(&self, event: &TradeEvent) -> Result<()> {
+        let mut errors = Vec::new();
+        for notifier in &self.0 {
+            if let Err(e) = notifier.send(event).await {
+                errors.push(format!("{:#}", e));
+            }
+        }
+
+        if !errors.is_empty() {
+            anyhow::bail!(
+                "{} of {} destinations failed: {}",
+                errors.len(),
+                self.0.len(),
+                errors.join("; ")
+            );
+        }
+        Ok(())
+    }
+}
+
+/// Builds the notifier for an account: its own `notifiers` if it has any, otherwise the global
+/// `notifier`. `telegram` is the shared bot, present when `telegram_token` is configured.
+pub fn for_account(
+    account: &AccountConfig,
+    config: &Config,
+    telegram: Option<&Arc<TelegramBot>>,
+) -> Result<Arc<dyn Notifier>> {
+    if account.notifiers.is_empty() {
+        return build(&config.notifier, config, telegram);
+    }
+
+    let mut notifiers = account
+        .notifiers
+        .iter()
+        .map(|notifier| build(notifier, config, telegram))
+        .collect::<Result<Vec<_>>>()
+        .with_context(|| format!("Invalid notifiers for account {}", account.name))?;
+
+    if notifiers.len() == 1 {
+        return Ok(notifiers.remove(0));
+    }
+    Ok(Arc::new(Fanout(notifiers)))
+}
+
+/// Builds a single notification backend.
+fn build(
+    notifier: &NotifierConfig,
+    config: &Config,
+    telegram: Option<&Arc<TelegramBot>>,
+) -> Result<Arc<dyn Notifier>> {
+    let markup = match notifier {
+        NotifierConfig::Telegram { .. } => Markup::Html,
+        NotifierConfig::Discord { .. } => Markup::Markdown,
+    };
+    let template = notifier
+        .template()
+        .map(|path| Template::load(path, markup))
+        .transpose()?;
+
+    match notifier {
+        NotifierConfig::Telegram {
+            chat_id,
+            thread_id,
+            silent,
+            ..
+        } => {
+            let Some(bot) = telegram else {
+                anyhow::bail!("Telegram notifier selected but telegram_token is missing.");
+            };
+            let chat_id = chat_id
+                .clone()
+                .unwrap_or_else(|| config.telegram_chat_id.clone());
+            if chat_id.is_empty() {
+                anyhow::bail!("Telegram notifier has no chat_id and telegram_chat_id is missing.");
+            }
+            let target = ChatTarget {
+                chat_id,
+                thread_id: *thread_id,
+                silent: *silent,
+            };
+            Ok(Arc::new(TelegramNotifier::new(
+                bot.clone(),
+                target,
+                template,
+            )))
+        }
+        NotifierConfig::Discord { webhook_url, .. } => {
+            Ok(Arc::new(DiscordWebhook::new(webhook_url.clone(), template)))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::outbox::Outbox;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    struct Counting {
+        sent: AtomicUsize,
+        fail: bool,
+    }
+
+    #[async_trait]
+    impl Notifier for Counting {
+        async fn send(&self, _event: &TradeEvent) -> Result<()> {
+            self.sent.fetch_add(1, Ordering::SeqCst);
+            if self.fail {
+                anyhow::bail!("unreachable");
+            }
+            Ok(())
+        }
+    }
+
+    fn event(kind: TradeEventKind, previous: Option<TradeOfferState>) -> TradeEvent {
+        let state = match kind {
+            TradeEventKind::Completed => TradeOfferState::Accepted,
+            _ => TradeOfferState::Declined,
+        };
+        let mut event = TradeEvent::new("Bot1", kind, "123", state);
+        event.previous_state = previous;
+        event
+    }
+
+    #[test]
+    fn test_state_line() {
+        let declined = event(
+            TradeEventKind::OfferStateChanged,
+            Some(TradeOfferState::Active),
+        );
+        assert_eq!(
+            declined.state_line().as_deref(),
+            Some("State: Active → Declined")
+        );
+
+        let released = event(TradeEventKind::Completed, Some(TradeOfferState::InEscrow));
+        assert_eq!(
+            released.state_line().as_deref(),
+            Some("State: Released from escrow")
+        );
+
+        assert!(
+            event(TradeEventKind::Completed, None)
+                .state_line()
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn test_message_escapes_item_names() {
+        let mut event = event(TradeEventKind::Completed, None);
+        event.items_received = vec!["\"<Gift> & Co\" Name Tag".to_string()];
+        event.message = Some("</b>hi".to_string());
+
+        let html = event.to_message().body_html();
+        assert_eq!(
+            html,
+            "Trade ID: 123\nMessage: &lt;/b&gt;hi\n\n<b>Received:</b>\n- \"&lt;Gift&gt; &amp; Co\" Name Tag"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_fanout_tries_every_destination() {
+        let failing = Arc::new(Counting {
+            sent: AtomicUsize::new(0),
+            fail: true,
+        });
+        let working = Arc::new(Counting {
+            sent: AtomicUsize::new(0),
+            fail: false,
+        });
+        let fanout = Fanout(vec![failing.clone(), working.clone()]);
+
+        let result = fanout.send(&event(TradeEventKind::Completed, None)).await;
+
+        assert!(result.unwrap_err().to_string().starts_with("1 of 2"));
+        assert_eq!(failing.sent.load(Ordering::SeqCst), 1);
+        assert_eq!(working.sent.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_telegram_destination_needs_a_chat() {
+        let config: Config = serde_json::from_str(
+            r#"{
+                "polling_interval_seconds": 30,
+                "accounts": [
+                    { "name": "Bot1", "api_key": "KEY1" },
+                    { "name": "Bot2", "api_key": "KEY2", "notifiers": [{ "type": "telegram" }] },
+                    { "name": "Bot3", "api_key": "KEY3", "notifiers": [
+                        { "type": "telegram", "chat_id": "-555" },
+                        { "type": "discord", "webhook_url": "https://discord.com/api/webhooks/1/abc" }
+                    ] }
+                ]
+            }"#,
+        )
+        .unwrap();
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_nanos();
+        let path = std::env::temp_dir().join(format!("test_notifier_outbox_{}.json", now));
+        let bot = Arc::new(TelegramBot::new(
+            "123:ABC".to_string(),
+            String::new(),
+            Outbox::new(&path).unwrap(),
+        ));
+
+        // No telegram_token at all.
+        assert!(for_account(&config.accounts[0], &config, None).is_err());
+        // A bot, but neither a chat_id nor telegram_chat_id.
+        assert!(for_account(&config.accounts[1], &config, Some(&bot)).is_err());
+        assert!(for_account(&config.accounts[2], &config, Some(&bot)).is_ok());
+
+        let _ = std::fs::remove_file(&path);
+    }
+}
@@ -0,0 +1,153 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::VecDeque,
+    fs,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+use tokio::sync::Notify;
+
+use crate::files::write_atomic;
+
+/// A message waiting to be delivered to a chat.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct OutboundMessage {
+    pub chat_id: String,
+    /// Forum topic within the chat.
+    #[serde(default)]
+    pub thread_id: Option<i64>,
+    /// Deliver without a notification sound.
+    #[serde(default)]
+    pub silent: bool,
+    /// Needs immediate attention; always delivered with a sound, even when `silent`.
+    #[serde(default)]
+    pub high_priority: bool,
+    pub text: String,
+}
+
+/// FIFO of undelivered messages, persisted to disk on every change so that nothing is lost
+/// if the process stops before they are sent.
+#[derive(Clone)]
+pub struct Outbox {
+    pending: Arc<Mutex<VecDeque<OutboundMessage>>>,
+    file_path: PathBuf,
+    wakeup: Arc<Notify>,
+}
+
+impl Outbox {
+    pub fn new<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let file_path = path.as_ref().to_path_buf();
+        let mut pending = VecDeque::new();
+
+        if file_path.exists() {
+            let content = fs::read_to_string(&file_path)
+                .with_context(|| format!("Failed to read outbox file: {:?}", file_path))?;
+            if !content.is_empty() {
+                pending = serde_json::from_str(&content)
+                    .with_context(|| format!("Failed to parse outbox file: {:?}", file_path))?;
+            }
+        }
+
+        Ok(Self {
+            pending: Arc::new(Mutex::new(pending)),
+            file_path,
+            wakeup: Arc::new(Notify::new()),
+        })
+    }
+
+    /// Queues messages back to back, so that the parts of a split message are never interleaved
+    /// with messages from other accounts.
+    pub fn extend(&self, messages: Vec<OutboundMessage>) -> Result<()> {
+        {
+            let mut pending = self
+                .pending
+                .lock()
+                .map_err(|_| anyhow::anyhow!("Lock poisoned"))?;
+            pending.extend(messages);
+            self.save(&pending)?;
+        }
+
+        self.wakeup.notify_one();
+        Ok(())
+    }
+
+    pub fn front(&self) -> Option<OutboundMessage> {
+        self.pending.lock().ok()?.front().cloned()
+    }
+
+    /// Removes the message at the front of the queue once it has been dealt with.
+    pub fn pop_front(&self) -> Result<()> {
+        let mut pending = self
+            .pending
+            .lock()
+            .map_err(|_| anyhow::anyhow!("Lock poisoned"))?;
+        pending.pop_front();
+        self.save(&pending)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn len(&self) -> usize {
+        self.pending.lock().map(|p| p.len()).unwrap_or(0)
+    }
+
+    /// Waits until messages are queued. Returns immediately if some were queued since the last wait.
+    pub async fn wait(&self) {
+        self.wakeup.notified().await;
+    }
+
+    fn save(&self, pending: &VecDeque<OutboundMessage>) -> Result<()> {
+        let content = serde_json::to_string_pretty(pending)?;
+        write_atomic(&self.file_path, &content, "outbox")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    fn get_temp_file_path() -> PathBuf {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_nanos();
+        let mut path = std::env::temp_dir();
+        path.push(format!("test_outbox_{}.json", now));
+        path
+    }
+
+    fn message(text: &str) -> OutboundMessage {
+        OutboundMessage {
+            chat_id: "-999".to_string(),
+            thread_id: None,
+            silent: false,
+            high_priority: false,
+            text: text.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_outbox_survives_restart() {
+        let path = get_temp_file_path();
+
+        {
+            let outbox = Outbox::new(&path).expect("Failed to create outbox");
+            outbox
+                .extend(vec![message("first"), message("second")])
+                .unwrap();
+            outbox.pop_front().unwrap();
+        }
+
+        {
+            let outbox = Outbox::new(&path).expect("Failed to load outbox");
+            assert_eq!(outbox.len(), 1);
+            assert_eq!(outbox.front(), Some(message("second")));
+        }
+
+        let _ = fs::remove_file(&path);
+    }
+}
@@ -0,0 +1,686 @@
+use anyhow::Result;
+use chrono::Utc;
+use log::{error, info, warn};
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::Arc,
+    time::Duration,
+};
+use tokio::{sync::watch, time::sleep};
+
+use crate::{
+    cache::{ItemCache, ItemKey},
+    config::{RuleAction, StateNotification},
+    ledger::Ledger,
+    models::{
+        Asset, TradeHistory, TradeOffer, TradeOfferState, TradeOffersResponseData,
+        account_id_to_steamid64,
+    },
+    notifier::{EventItem, Notifier, Priority, ReplacedOffer, TradeEvent, TradeEventKind},
+    pricing::{PriceClient, value_trade},
+    profiles::{Partner, ProfileCache},
+    rules::RuleSet,
+    scam::RedirectDetector,
+    state::{AccountState, OfferRecord, StateStore},
+    status::StatusRegistry,
+    steam::{SteamClient, find_class_info},
+};
+
+/// Polls are up to this fraction of the interval early or late.
+const POLL_JITTER: f64 = 0.1;
+
+/// The parts of a poller's configuration that can change while it runs.
+pub struct PollerSettings {
+    pub polling_interval: Duration,
+    pub notifier: Arc<dyn Notifier>,
+    pub rules: RuleSet,
+    pub notify_incoming_offers: bool,
+    pub state_notifications: Vec<StateNotification>,
+    pub detect_trade_redirects: bool,
+}
+
+/// Watches a single Steam account and sends notifications for its trades.
+pub struct Poller {
+    pub account_name: String,
+    pub client: SteamClient,
+    pub cache: Arc<ItemCache>,
+    pub profiles: ProfileCache,
+    pub pricer: Option<PriceClient>,
+    pub state_store: StateStore,
+    pub ledger: Ledger,
+    pub status: StatusRegistry,
+    /// Updated when the config is reloaded.
+    pub settings: watch::Receiver<Arc<PollerSettings>>,
+    /// Set to ask the poller to stop. It finishes the current poll, and saves its state, first.
+    pub stop: watch::Receiver<bool>,
+}
+
+impl Poller {
+    pub async fn run(self) {
+        info!("[{}] Poller started.", self.account_name);
+        self.status.register(&self.account_name);
+
+        let mut state = self.state_store.get(&self.account_name);
+        let mut redirects = RedirectDetector::default();
+
+        // Resume from the saved cursor, or ignore trades completed before the first startup.
+        let cutoff = if state.last_poll_time > 0 {
+            info!(
+                "[{}] Resuming from last poll at {}, checking for missed trades...",
+                self.account_name, state.last_poll_time
+            );
+            let since = state.last_poll_time;
+            self.backfill_trades(&mut state, since).await;
+            state.last_poll_time
+        } else {
+            Utc::now().timestamp() as u64
+        };
+        state.last_poll_time = cutoff;
+
+        let mut settings = self.settings.clone();
+        let mut stop = self.stop.clone();
+
+        // Start at a random point in the interval, so accounts don't all poll at once.
+        let offset = settings.borrow().polling_interval.mul_f64(rand::random());
+        tokio::select! {
+            _ = sleep(offset) => {}
+            Ok(()) = stop.changed() => {}
+        }
+
+        while !*stop.borrow() {
+            if self.status.is_paused(&self.account_name) {
+                self.wait(&mut settings, &mut stop).await;
+                continue;
+            }
+
+            // Poll
+            match self
+                .client
+                .get_active_trade_offers(state.last_poll_time)
+                .await
+            {
+                Ok(offers) => {
+                    self.process_offers(&mut state, &mut redirects, cutoff, offers.response)
+                        .await;
+
+                    self.status.record_success(&self.account_name);
+
+                    // Update cutoff timestamp.
+                    state.last_poll_time = Utc::now().timestamp() as u64 - 60;
+                    state.prune();
+                    if let Err(e) = self.state_store.set(&self.account_name, state.clone()) {
+                        error!("[{}] Failed to save poller state: {}", self.account_name, e);
+                    }
+                }
+                Err(e) => {
+                    error!("[{}] Metadata poll failed: {}", self.account_name, e);
+                    self.status.record_error(&self.account_name, e.to_string());
+                }
+            }
+
+            self.wait(&mut settings, &mut stop).await;
+        }
+        info!("[{}] Poller stopped.", self.account_name);
+    }
+
+    /// Sleeps for the polling interval, give or take a little so that accounts stay spread out.
+    /// A config reload cuts the wait short, so that a new interval takes effect straight away,
+    /// as does a request to stop.
+    async fn wait(
+        &self,
+        settings: &mut watch::Receiver<Arc<PollerSettings>>,
+        stop: &mut watch::Receiver<bool>,
+    ) {
+        let jitter = rand::random_range(1.0 - POLL_JITTER..=1.0 + POLL_JITTER);
+        let interval = settings
+            .borrow_and_update()
+            .polling_interval
+            .mul_f64(jitter);
+        tokio::select! {
+            _ = sleep(interval) => {}
+            Ok(()) = settings.changed() => {}
+            Ok(()) = stop.changed() => {}
+        }
+    }
+
+    fn settings(&self) -> Arc<PollerSettings> {
+        self.settings.borrow().clone()
+    }
+
+    /// Notifies about trades completed since `since` that haven't been notified yet, and saves
+    /// the result. Used by the `backfill` subcommand; `run` does this itself on startup.
+    pub async fn backfill(&self, since: u64) -> Result<()> {
+        let mut state = self.state_store.get(&self.account_name);
+        self.backfill_trades(&mut state, since).await;
+        self.state_store.set(&self.account_name, state)
+    }
+
+    async fn process_offers(
+        &self,
+        state: &mut AccountState,
+        redirects: &mut RedirectDetector,
+        cutoff: u64,
+        offers: TradeOffersResponseData,
+    ) {
+        let settings = self.settings();
+        let cutoff = state.effective_cutoff(cutoff);
+        let mut new_trades = Vec::new();
+        let mut new_incoming = Vec::new();
+        let mut transitions = Vec::new();
+
+        let received = offers.trade_offers_received.into_iter().map(|o| (o, true));
+        let sent = offers.trade_offers_sent.into_iter().map(|o| (o, false));
+
+        for (offer, is_received) in received.chain(sent) {
+            let current = offer.trade_offer_state;
+            let previous = state.offer_states.get(&offer.tradeofferid).map(|r| r.state);
+            if previous == Some(current) {
+                continue;
+            }
+            state.offer_states.insert(
+                offer.tradeofferid.clone(),
+                OfferRecord {
+                    state: current,
+                    time_updated: offer.time_updated,
+                },
+            );
+
+            // Ignore changes made before the resume cutoff, or too long ago to be remembered
+            if offer.time_updated < cutoff {
+                continue;
+            }
+
+            if !is_received && settings.detect_trade_redirects {
+                redirects.observe(&offer, previous);
+            }
+
+            if current == TradeOfferState::Accepted {
+                // Accepted offers are reported as completed trades, with the full item list.
+                if !state.processed_offers.contains_key(&offer.tradeofferid) {
+                    new_trades.push((offer, previous));
+                }
+            } else if is_received
+                && settings.notify_incoming_offers
+                && current == TradeOfferState::Active
+                && previous.is_none()
+            {
+                new_incoming.push(offer);
+            } else if settings
+                .state_notifications
+                .iter()
+                .any(|rule| rule.matches(previous, current))
+            {
+                transitions.push((offer, previous));
+            }
+        }
+
+        // Raise the alarm on suspected redirects before anything else
+        for redirect in redirects.take_redirects(Utc::now().timestamp() as u64) {
+            warn!(
+                "[{}] Possible trade redirect: offer {} was canceled and replaced by {}",
+                self.account_name,
+                redirect.canceled.tradeofferid,
+                redirect.replacement.tradeofferid
+            );
+            let replaced = ReplacedOffer {
+                tradeofferid: redirect.canceled.tradeofferid.clone(),
+                partner: self
+                    .partner_for(account_id_to_steamid64(redirect.canceled.accountid_other))
+                    .await,
+            };
+            match self
+                .build_offer_event(&redirect.replacement, TradeEventKind::SuspectedRedirect)
+                .await
+            {
+                Ok(mut event) => {
+                    event.priority = Priority::High;
+                    event.replaced_offer = Some(replaced);
+                    self.send(&event).await;
+                }
+                Err(e) => error!(
+                    "[{}] Failed to process offer details: {}",
+                    self.account_name, e
+                ),
+            }
+        }
+
+        // Announce new incoming offers
+        for offer in new_incoming {
+            info!(
+                "[{}] Found new incoming offer: {}",
+                self.account_name, offer.tradeofferid
+            );
+            self.notify_offer(&offer, TradeEventKind::IncomingOffer, None)
+                .await;
+        }
+
+        // Announce state transitions
+        for (offer, previous) in transitions {
+            info!(
+                "[{}] Offer {} changed state: {} -> {}",
+                self.account_name,
+                offer.tradeofferid,
+                previous.map_or("New".to_string(), |s| s.to_string()),
+                offer.trade_offer_state
+            );
+            self.notify_offer(&offer, TradeEventKind::OfferStateChanged, previous)
+                .await;
+        }
+
+        // Process new trades
+        for (trade, previous) in new_trades {
+            info!(
+                "[{}] Found new completed trade: {}",
+                self.account_name, trade.tradeofferid
+            );
+            state
+                .processed_offers
+                .insert(trade.tradeofferid.clone(), trade.time_updated);
+
+            // Fetch History to get items
+            let hist = match find_trade_history(&self.client, &trade).await {
+                Ok(Some(hist)) => hist,
+                Ok(None) => {
+                    warn!(
+                        "[{}] Trade history not found for {}, skipping notification.",
+                        self.account_name, trade.tradeofferid
+                    );
+                    continue;
+                }
+                Err(e) => {
+                    error!(
+                        "[{}] Failed to process trade details: {}",
+                        self.account_name, e
+                    );
+                    continue;
+                }
+            };
+
+            if state.processed_trades.contains_key(&hist.tradeid) {
+                info!(
+                    "[{}] Trade {} was already notified, skipping.",
+                    self.account_name, hist.tradeid
+                );
+                continue;
+            }
+
+            self.notify_trade(state, hist, previous).await;
+        }
+    }
+
+    /// Notifies every trade that completed since `since` but was never processed, e.g. because
+    /// it happened while TradeBell was restarting.
+    async fn backfill_trades(&self, state: &mut AccountState, since: u64) {
+        let missed = match self.client.get_trade_history_since(since).await {
+            Ok(trades) => trades,
+            Err(e) => {
+                error!(
+                    "[{}] Failed to backfill missed trades: {}",
+                    self.account_name, e
+                );
+                return;
+            }
+        };
+
+        // History is newest first; notify in the order the trades happened.
+        for hist in missed.into_iter().rev() {
+            if state.processed_trades.contains_key(&hist.tradeid) {
+                continue;
+            }
+            info!(
+                "[{}] Backfilling missed trade: {}",
+                self.account_name, hist.tradeid
+            );
+            self.notify_trade(state, hist, None).await;
+        }
+    }
+
+    /// Builds and sends the notification for a trade, stores it in the ledger and records it
+    /// as processed.
+    async fn notify_trade(
+        &self,
+        state: &mut AccountState,
+        hist: TradeHistory,
+        previous: Option<TradeOfferState>,
+    ) {
+        state
+            .processed_trades
+            .insert(hist.tradeid.clone(), hist.time_init);
+
+        let event = self.build_trade_event(&hist).await;
+
+        // Record the trade after building the event, so item descriptions are cached by now.
+        if let Err(e) = self
+            .ledger
+            .record_trade(&self.account_name, &hist, |asset| {
+                self.cache.get(&ItemKey::of(asset))
+            })
+        {
+            error!(
+                "[{}] Failed to record trade {} in the ledger: {}",
+                self.account_name, hist.tradeid, e
+            );
+        }
+
+        match event {
+            Ok(mut event) => {
+                event.previous_state = previous;
+                self.send(&event).await;
+            }
+            Err(e) => error!(
+                "[{}] Failed to process trade details: {}",
+                self.account_name, e
+            ),
+        }
+    }
+
+    async fn notify_offer(
+        &self,
+        offer: &TradeOffer,
+        kind: TradeEventKind,
+        previous: Option<TradeOfferState>,
+    ) {
+        match self.build_offer_event(offer, kind).await {
+            Ok(mut event) => {
+                event.previous_state = previous;
+                self.send(&event).await;
+            }
+            Err(e) => error!(
+                "[{}] Failed to process offer details: {}",
+                self.account_name, e
+            ),
+        }
+    }
+
+    async fn build_trade_event(&self, hist: &TradeHistory) -> Result<TradeEvent> {
+        let mut event = TradeEvent::new(
+            &self.account_name,
+            TradeEventKind::Completed,
+            &hist.tradeid,
+            TradeOfferState::Accepted,
+        );
+        event.partner = Some(Partner::new(hist.steamid_other.clone()));
+
+        let received = hist.assets_received.as_deref().unwrap_or_default();
+        let given = hist.assets_given.as_deref().unwrap_or_default();
+        self.describe_items(&mut event, received, given).await?;
+
+        Ok(event)
+    }
+
+    async fn build_offer_event(
+        &self,
+        offer: &TradeOffer,
+        kind: TradeEventKind,
+    ) -> Result<TradeEvent> {
+        let mut event = TradeEvent::new(
+            &self.account_name,
+            kind,
+            &offer.tradeofferid,
+            offer.trade_offer_state,
+        );
+        event.partner = Some(Partner::new(
+            account_id_to_steamid64(offer.accountid_other).to_string(),
+        ));
+        event.message = offer.message.clone().filter(|m| !m.is_empty());
+
+        let received = offer.items_to_receive.clone().unwrap_or_default();
+        let given = offer.items_to_give.clone().unwrap_or_default();
+        self.describe_items(&mut event, &received, &given).await?;
+
+        Ok(event)
+    }
+
+    /// Fills in the item lists of an event, and their market value if pricing is enabled.
+    async fn describe_items(
+        &self,
+        event: &mut TradeEvent,
+        received: &[Asset],
+        given: &[Asset],
+    ) -> Result<()> {
+        // Process Received
+        if !received.is_empty() {
+            let names = resolve_asset_names(&self.client, &self.cache, received).await?;
+            event.items_received = group_and_format_items(names);
+        }
+
+        // Process Given
+        if !given.is_empty() {
+            let names = resolve_asset_names(&self.client, &self.cache, given).await?;
+            event.items_given = group_and_format_items(names);
+        }
+
+        let received_items = received.iter().map(|asset| (true, asset));
+        let given_items = given.iter().map(|asset| (false, asset));
+        event.items = received_items
+            .chain(given_items)
+            .map(|(received, asset)| {
+                let info = self.cache.get(&ItemKey::of(asset));
+                EventItem {
+                    appid: asset.appid,
+                    market_hash_name: info.as_ref().map(|i| i.market_hash_name.clone()),
+                    type_: info.map(|i| i.type_),
+                    received,
+                }
+            })
+            .collect();
+
+        if let Some(pricer) = &self.pricer {
+            event.valuation = Some(
+                value_trade(
+                    pricer,
+                    &self.market_names(received),
+                    &self.market_names(given),
+                )
+                .await,
+            );
+        }
+
+        Ok(())
+    }
+
+    /// The (appid, market_hash_name) of every asset whose description is known.
+    fn market_names(&self, assets: &[Asset]) -> Vec<(u32, String)> {
+        assets
+            .iter()
+            .filter_map(|asset| {
+                self.cache
+                    .get(&ItemKey::of(asset))
+                    .map(|info| (asset.appid, info.market_hash_name))
+            })
+            .collect()
+    }
+
+    async fn partner_for(&self, steamid: u64) -> Partner {
+        let mut partner = Partner::new(steamid.to_string());
+        self.profiles.resolve(&self.client, &mut partner).await;
+        partner
+    }
+
+    /// Applies the filtering rules, then resolves the partner's profile and sends the event to
+    /// the notifier. Redirect alerts are never filtered.
+    async fn send(&self, event: &TradeEvent) {
+        let settings = self.settings();
+        let mut event = event.clone();
+        if event.kind != TradeEventKind::SuspectedRedirect {
+            match settings.rules.evaluate(&event) {
+                RuleAction::Include => {}
+                RuleAction::Exclude => {
+                    info!(
+                        "[{}] Not notifying about {} {}: excluded by a rule.",
+                        self.account_name,
+                        event.kind.name(),
+                        event.trade_id
+                    );
+                    return;
+                }
+                RuleAction::Priority => event.priority = Priority::High,
+            }
+        }
+
+        if let Some(partner) = &mut event.partner {
+            self.profiles.resolve(&self.client, partner).await;
+        }
+
+        if let Err(e) = settings.notifier.send(&event).await {
+            error!("[{}] Failed to send notification: {}", self.account_name, e);
+        }
+    }
+}
+
+async fn find_trade_history(
+    client: &SteamClient,
+    trade: &TradeOffer,
+) -> Result<Option<TradeHistory>> {
+    // Accepted offers carry the ID of the trade they produced; match on it exactly.
+    if let Some(tradeid) = &trade.tradeid {
+        return client.find_trade_by_id(tradeid, trade.time_created).await;
+    }
+
+    // Without a trade ID, fall back to the trade history entry closest to the `time_updated`
+    // of the trade offer. The most recent page is enough as the offer was only just accepted.
+    let history_response = client.get_trade_history(None).await?;
+
+    let mut best_match: Option<TradeHistory> = None;
+    for hist in history_response.response.trades {
+        // time_init is when trade started/completed.
+        let time_diff = (hist.time_init as i64 - trade.time_updated as i64).abs();
+        if time_diff < 120 {
+            // within 2 minutes
+            best_match = Some(hist);
+            break; // Assume first (newest) one is correct? API returns newest first.
+        }
+    }
+
+    Ok(best_match)
+}
+
+async fn resolve_asset_names(
+    client: &SteamClient,
+    cache: &ItemCache,
+    assets: &[Asset],
+) -> Result<Vec<String>> {
+    let mut names = Vec::new();
+    let mut asset_map: HashMap<ItemKey, String> = HashMap::new(); // Item -> Name
+
+    // 1. Check Cache
+    for asset in assets {
+        let key = ItemKey::of(asset);
+        if let Some(info) = cache.get(&key) {
+            asset_map.insert(key, info.market_hash_name);
+        }
+    }
+
+    // 2. Fetch missing, grouped by AppID to batch API requests.
+    let mut by_appid: HashMap<u32, Vec<(String, String)>> = HashMap::new();
+    for asset in assets {
+        if !asset_map.contains_key(&ItemKey::of(asset)) {
+            by_appid
+                .entry(asset.appid)
+                .or_default()
+                .push((asset.classid.clone(), asset.instanceid.clone()));
+        }
+    }
+
+    for (appid, mut unique_pairs) in by_appid {
+        unique_pairs.sort();
+        unique_pairs.dedup();
+
+        match client.get_asset_class_info(appid, &unique_pairs).await {
+            Ok(info_map) => {
+                for (cid, iid) in unique_pairs {
+                    if let Some(info) = find_class_info(&info_map, &cid, &iid) {
+                        let key = ItemKey::new(appid, &cid, &iid);
+                        cache.insert(key.clone(), info.clone()).unwrap_or_default();
+                        asset_map.insert(key, info.market_hash_name.clone());
+                    }
+                }
+            }
+            Err(e) => error!("Failed to enrich items for app {}: {}", appid, e),
+        }
+    }
+
+    // 3. Construct names list
+    for asset in assets {
+        if let Some(name) = asset_map.get(&ItemKey::of(asset)) {
+            names.push(name.clone());
+        } else {
+            names.push(format!(
+                "Unknown Asset ({})",
+                asset.market_name_or_fallback()
+            ));
+        }
+    }
+
+    Ok(names)
+}
+
+trait AssetFallback {
+    fn market_name_or_fallback(&self) -> String;
+}
+
+impl AssetFallback for Asset {
+    fn market_name_or_fallback(&self) -> String {
+        // We don't have the name in the Asset struct itself from history?
+        // History `assets_received` often just has IDs.
+        format!("ID: {}", self.assetid)
+    }
+}
+
+fn group_and_format_items(items: Vec<String>) -> Vec<String> {
+    let mut counts = BTreeMap::new();
+    for item in items {
+        *counts.entry(item).or_insert(0) += 1;
+    }
+
+    counts
+        .into_iter()
+        .map(|(name, count)| {
+            if count > 1 {
+                format!("{}x {}", count, name)
+            } else {
+                name
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_group_items_single() {
+        let items = vec!["Key".to_string()];
+        let result = group_and_format_items(items);
+        assert_eq!(result, vec!["Key"]);
+    }
+
+    #[test]
+    fn test_group_items_multiple_distinct() {
+        let items = vec!["Key".to_string(), "Crate".to_string()];
+        // BTreeMap sorts keys, so Crate comes before Key
+        let result = group_and_format_items(items);
+        assert_eq!(result, vec!["Crate", "Key"]);
+    }
+
+    #[test]
+    fn test_group_items_duplicates() {
+        let items = vec!["Key".to_string(), "Key".to_string(), "Key".to_string()];
+        let result = group_and_format_items(items);
+        assert_eq!(result, vec!["3x Key"]);
+    }
+
+    #[test]
+    fn test_group_items_mixed() {
+        let items = vec![
+            "Key".to_string(),
+            "Crate".to_string(),
+            "Key".to_string(),
+            "Hat".to_string(),
+        ];
+        // Sorted: Crate, Hat, Key (2)
+        let result = group_and_format_items(items);
+        assert_eq!(result, vec!["Crate", "Hat", "2x Key"]);
+    }
+}
@@ -0,0 +1,447 @@
+use anyhow::{Context, Result};
+use log::warn;
+use reqwest::{Client, StatusCode, header::RETRY_AFTER};
+use serde::Deserialize;
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::{Arc, Mutex, RwLock},
+    time::{Duration, Instant},
+};
+
+use crate::{
+    config::{PricingConfig, SteamRateLimitConfig},
+    ratelimit::RateLimiter,
+};
+
+/// The market answers 429 after roughly 20 price lookups a minute from one address.
+const MARKET_RATE_LIMIT: SteamRateLimitConfig = SteamRateLimitConfig {
+    requests_per_minute: 20,
+    burst: 5,
+};
+/// How long a failed lookup is remembered, so a failing item isn't asked for on every trade.
+const FAILURE_TTL: Duration = Duration::from_secs(5 * 60);
+/// How long to stop asking after a 429 that doesn't say.
+const DEFAULT_BACKOFF: Duration = Duration::from_secs(5 * 60);
+/// Most uncached prices looked up for one trade; the rest of its items are left unpriced, so
+/// that a large trade doesn't hold up its notification.
+const MAX_LOOKUPS_PER_TRADE: usize = 10;
+
+/// Lowest and median Community Market prices, in the currency's minor units (e.g. cents).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MarketPrice {
+    pub lowest: Option<u64>,
+    pub median: Option<u64>,
+}
+
+impl MarketPrice {
+    /// The price used for valuation: the median sale price, or the lowest listing if there
+    /// have been no recent sales.
+    pub fn value(&self) -> Option<u64> {
+        self.median.or(self.lowest)
+    }
+}
+
+#[derive(Deserialize)]
+struct PriceOverview {
+    success: bool,
+    lowest_price: Option<String>,
+    median_price: Option<String>,
+}
+
+/// (appid, market_hash_name) -> price, or `None` if not marketable or the lookup failed, and
+/// when that stops being trusted.
+type PriceCache = HashMap<(u32, String), (Option<MarketPrice>, Instant)>;
+
+/// Looks up item prices on the Steam Community Market, caching results (including misses)
+/// for the configured time, and failures for a few minutes.
+#[derive(Clone)]
+pub struct PriceClient {
+    client: Client,
+    currency: u32,
+    ttl: Duration,
+    cache: Arc<RwLock<PriceCache>>,
+    limiter: RateLimiter,
+    /// No lookups are made until then, after the market answered 429.
+    backoff_until: Arc<Mutex<Option<Instant>>>,
+}
+
+impl PriceClient {
+    pub fn new(config: &PricingConfig) -> Self {
+        Self {
+            client: Client::new(),
+            currency: config.currency,
+            ttl: Duration::from_secs(config.cache_ttl_seconds),
+            cache: Arc::new(RwLock::new(HashMap::new())),
+            limiter: RateLimiter::new(&MARKET_RATE_LIMIT),
+            backoff_until: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    pub fn currency(&self) -> u32 {
+        self.currency
+    }
+
+    /// The cached price, or `None` if it has to be looked up.
+    fn cached_price(&self, appid: u32, market_hash_name: &str) -> Option<Option<MarketPrice>> {
+        let cache = self.cache.read().ok()?;
+        let (price, expires) = cache.get(&(appid, market_hash_name.to_string()))?;
+        (Instant::now() < *expires).then_some(*price)
+    }
+
+    fn remember(
+        &self,
+        appid: u32,
+        market_hash_name: &str,
+        price: Option<MarketPrice>,
+        ttl: Duration,
+    ) {
+        if let Ok(mut cache) = self.cache.write() {
+            let now = Instant::now();
+            cache.retain(|_, (_, expires)| now < *expires);
+            cache.insert((appid, market_hash_name.to_string()), (price, now + ttl));
+        }
+    }
+
+    fn backing_off(&self) -> bool {
+        self.backoff_until
+            .lock()
+            .ok()
+            .and_then(|until| *until)
+            .is_some_and(|until| Instant::now() < until)
+    }
+
+    pub async fn get_price(&self, appid: u32, market_hash_name: &str) -> Option<MarketPrice> {
+        if let Some(price) = self.cached_price(appid, market_hash_name) {
+            return price;
+        }
+        if self.backing_off() {
+            return None;
+        }
+
+        self.limiter.acquire().await;
+        match self.fetch_price(appid, market_hash_name).await {
+            Ok(price) => {
+                self.remember(appid, market_hash_name, price, self.ttl);
+                price
+            }
+            Err(e) => {
+                warn!("Failed to fetch price for {}: {}", market_hash_name, e);
+                self.remember(appid, market_hash_name, None, FAILURE_TTL);
+                None
+            }
+        }
+    }
+
+    async fn fetch_price(&self, appid: u32, market_hash_name: &str) -> Result<Option<MarketPrice>> {
+        let url = "https://steamcommunity.com/market/priceoverview/";
+
+        let response = self
+            .client
+            .get(url)
+            .query(&[
+                ("appid", appid.to_string()),
+                ("currency", self.currency.to_string()),
+                ("market_hash_name", market_hash_name.to_string()),
+            ])
+            .send()
+            .await
+            .map_err(|e| e.without_url())
+            .context("Failed to fetch price overview")?;
+
+        if response.status() == StatusCode::TOO_MANY_REQUESTS {
+            let delay = response
+                .headers()
+                .get(RETRY_AFTER)
+                .and_then(|value| value.to_str().ok()?.parse().ok())
+                .map_or(DEFAULT_BACKOFF, Duration::from_secs);
+            if let Ok(mut until) = self.backoff_until.lock() {
+                *until = Some(Instant::now() + delay);
+            }
+            anyhow::bail!(
+                "Steam Market rate limit hit, pausing price lookups for {}s",
+                delay.as_secs()
+            );
+        }
+        if !response.status().is_success() {
+            let error_text = response
+                .text()
+                .await
+                .unwrap_or_else(|_| "Unknown error".to_string());
+            anyhow::bail!("Steam Market error (priceoverview): {}", error_text);
+        }
+
+        let overview = response
+            .json::<PriceOverview>()
+            .await
+            .context("Failed to parse price overview")?;
+
+        // Items that aren't marketable come back with `success: false`.
+        if !overview.success {
+            return Ok(None);
+        }
+
+        Ok(Some(MarketPrice {
+            lowest: overview.lowest_price.as_deref().and_then(parse_price),
+            median: overview.median_price.as_deref().and_then(parse_price),
+        }))
+    }
+}
+
+/// Parses a market price string such as "$1,234.56", "1 234,56€" or "¥ 1200" into minor units.
+pub fn parse_price(text: &str) -> Option<u64> {
+    let cleaned: String = text
+        .chars()
+        .filter(|c| c.is_ascii_digit() || *c == '.' || *c == ',')
+        .collect();
+    let cleaned = cleaned.trim_matches(|c| c == '.' || c == ',');
+    if cleaned.is_empty() {
+        return None;
+    }
+
+    // A separator followed by exactly two digits at the end is the decimal point;
+    // every other separator groups thousands.
+    let (whole, fraction) = match cleaned.rfind(['.', ',']) {
+        Some(i) if cleaned.len() - i == 3 => (&cleaned[..i], &cleaned[i + 1..]),
+        _ => (cleaned, "00"),
+    };
+
+    let whole: String = whole.chars().filter(|c| c.is_ascii_digit()).collect();
+    let whole: u64 = if whole.is_empty() {
+        0
+    } else {
+        whole.parse().ok()?
+    };
+    let fraction: u64 = fraction.parse().ok()?;
+
+    Some(whole * 100 + fraction)
+}
+
+/// Formats an amount in minor units with the symbol of a Steam currency code.
+pub fn format_amount(amount: i64, currency: u32) -> String {
+    let sign = if amount < 0 { "-" } else { "" };
+    let abs = amount.unsigned_abs();
+    let number = format!("{}.{:02}", abs / 100, abs % 100);
+
+    match currency {
+        1 => format!("{}${}", sign, number),
+        2 => format!("{}£{}", sign, number),
+        3 => format!("{}{}€", sign, number),
+        _ => format!("{}{}", sign, number),
+    }
+}
+
+/// The value of one group of identical items.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ItemValue {
+    pub name: String,
+    pub count: u32,
+    /// Unit price in minor units, `None` if the item has no market price.
+    pub unit_price: Option<u64>,
+}
+
+impl ItemValue {
+    pub fn total(&self) -> Option<u64> {
+        self.unit_price.map(|p| p * self.count as u64)
+    }
+}
+
+/// Market valuation of both sides of a trade.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Valuation {
+    pub currency: u32,
+    pub received: Vec<ItemValue>,
+    pub given: Vec<ItemValue>,
+}
+
+impl Valuation {
+    pub fn total_received(&self) -> u64 {
+        self.received.iter().filter_map(ItemValue::total).sum()
+    }
+
+    pub fn total_given(&self) -> u64 {
+        self.given.iter().filter_map(ItemValue::total).sum()
+    }
+
+    /// How much more we received than we gave.
+    pub fn net(&self) -> i64 {
+        self.total_received() as i64 - self.total_given() as i64
+    }
+
+    pub fn format(&self, amount: i64) -> String {
+        format_amount(amount, self.currency)
+    }
+
+    /// One line per priced item group, e.g. "+ 2x Key: $4.20 ($2.10 each)".
+    pub fn item_lines(&self) -> Vec<String> {
+        let received = self.received.iter().map(|v| ("+", v));
+        let given = self.given.iter().map(|v| ("-", v));
+
+        received
+            .chain(given)
+            .map(|(sign, value)| {
+                let label = if value.count > 1 {
+                    format!("{}x {}", value.count, value.name)
+                } else {
+                    value.name.clone()
+                };
+                match (value.unit_price, value.total()) {
+                    (Some(unit), Some(total)) if value.count > 1 => format!(
+                        "{} {}: {} ({} each)",
+                        sign,
+                        label,
+                        self.format(total as i64),
+                        self.format(unit as i64)
+                    ),
+                    (_, Some(total)) => {
+                        format!("{} {}: {}", sign, label, self.format(total as i64))
+                    }
+                    _ => format!("{} {}: n/a", sign, label),
+                }
+            })
+            .collect()
+    }
+
+    /// "Received: $4.20 | Given: $1.00 | Net: +$3.20"
+    pub fn summary_line(&self) -> String {
+        let net = self.net();
+        format!(
+            "Received: {} | Given: {} | Net: {}{}",
+            self.format(self.total_received() as i64),
+            self.format(self.total_given() as i64),
+            if net > 0 { "+" } else { "" },
+            self.format(net)
+        )
+    }
+}
+
+/// Prices both sides of a trade, given as (appid, market_hash_name) items. At most
+/// `MAX_LOOKUPS_PER_TRADE` prices that aren't cached are looked up; other items are unpriced.
+pub async fn value_trade(
+    pricer: &PriceClient,
+    received: &[(u32, String)],
+    given: &[(u32, String)],
+) -> Valuation {
+    let mut lookups = MAX_LOOKUPS_PER_TRADE;
+    Valuation {
+        currency: pricer.currency(),
+        received: value_items(pricer, received, &mut lookups).await,
+        given: value_items(pricer, given, &mut lookups).await,
+    }
+}
+
+/// Prices a list of items, grouping identical ones, using up to `lookups` uncached lookups.
+async fn value_items(
+    pricer: &PriceClient,
+    items: &[(u32, String)],
+    lookups: &mut usize,
+) -> Vec<ItemValue> {
+    let mut counts: BTreeMap<&(u32, String), u32> = BTreeMap::new();
+    for item in items {
+        *counts.entry(item).or_insert(0) += 1;
+    }
+
+    let mut values = Vec::new();
+    for ((appid, name), count) in counts {
+        let price = match pricer.cached_price(*appid, name) {
+            Some(price) => price,
+            None if *lookups > 0 => {
+                *lookups -= 1;
+                pricer.get_price(*appid, name).await
+            }
+            None => None,
+        };
+        let unit_price = price.and_then(|p| p.value());
+        values.push(ItemValue {
+            name: name.clone(),
+            count,
+            unit_price,
+        });
+    }
+    values
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_price() {
+        assert_eq!(parse_price("$2.10"), Some(210));
+        assert_eq!(parse_price("$1,234.56"), Some(123456));
+        assert_eq!(parse_price("1 234,56€"), Some(123456));
+        assert_eq!(parse_price("0,03€"), Some(3));
+        assert_eq!(parse_price("¥ 1,200"), Some(120000));
+        assert_eq!(parse_price("--"), None);
+    }
+
+    #[test]
+    fn test_valuation_totals() {
+        let valuation = Valuation {
+            currency: 1,
+            received: vec![ItemValue {
+                name: "Key".to_string(),
+                count: 2,
+                unit_price: Some(210),
+            }],
+            given: vec![
+                ItemValue {
+                    name: "Crate".to_string(),
+                    count: 1,
+                    unit_price: Some(5),
+                },
+                ItemValue {
+                    name: "Gift".to_string(),
+                    count: 1,
+                    unit_price: None,
+                },
+            ],
+        };
+
+        assert_eq!(valuation.total_received(), 420);
+        assert_eq!(valuation.total_given(), 5);
+        assert_eq!(valuation.net(), 415);
+        assert_eq!(
+            valuation.item_lines(),
+            vec![
+                "+ 2x Key: $4.20 ($2.10 each)",
+                "- Crate: $0.05",
+                "- Gift: n/a"
+            ]
+        );
+        assert_eq!(
+            valuation.summary_line(),
+            "Received: $4.20 | Given: $0.05 | Net: +$4.15"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_prices_from_cache_while_backing_off() {
+        let pricer = PriceClient::new(&PricingConfig {
+            currency: 1,
+            cache_ttl_seconds: 3600,
+        });
+        let key = MarketPrice {
+            lowest: Some(250),
+            median: Some(210),
+        };
+        pricer.remember(440, "Key", Some(key), Duration::from_secs(60));
+        pricer.remember(440, "Broken", None, FAILURE_TTL);
+        *pricer.backoff_until.lock().unwrap() = Some(Instant::now() + DEFAULT_BACKOFF);
+
+        let valuation = value_trade(
+            &pricer,
+            &[(440, "Key".to_string()), (440, "Key".to_string())],
+            &[(440, "Broken".to_string()), (440, "Crate".to_string())],
+        )
+        .await;
+
+        assert_eq!(valuation.total_received(), 420);
+        assert!(valuation.given.iter().all(|v| v.unit_price.is_none()));
+    }
+
+    #[test]
+    fn test_format_negative_amount() {
+        assert_eq!(format_amount(-150, 1), "-$1.50");
+        assert_eq!(format_amount(150, 3), "1.50€");
+    }
+}
@@ -0,0 +1,122 @@
+use log::warn;
+use std::{
+    collections::HashMap,
+    sync::{Arc, RwLock},
+    time::{Duration, Instant},
+};
+
+use crate::{models::PlayerSummary, steam::SteamClient};
+
+/// How long a resolved persona name and avatar are reused before asking Steam again.
+const PROFILE_TTL: Duration = Duration::from_secs(6 * 60 * 60);
+
+/// A trade partner, identified by SteamID64 and enriched with their public profile when available.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Partner {
+    pub steamid: String,
+    pub name: Option<String>,
+    pub avatar_url: Option<String>,
+}
+
+impl Partner {
+    pub fn new(steamid: String) -> Self {
+        Self {
+            steamid,
+            name: None,
+            avatar_url: None,
+        }
+    }
+
+    pub fn profile_url(&self) -> String {
+        format!("https://steamcommunity.com/profiles/{}", self.steamid)
+    }
+
+    /// The persona name, or the SteamID64 if the profile could not be resolved.
+    pub fn display_name(&self) -> &str {
+        self.name.as_deref().unwrap_or(&self.steamid)
+    }
+}
+
+/// In-memory cache of player summaries shared by all pollers.
+#[derive(Clone, Default)]
+pub struct ProfileCache {
+    data: Arc<RwLock<HashMap<String, (PlayerSummary, Instant)>>>,
+}
+
+impl ProfileCache {
+    pub fn get(&self, steamid: &str) -> Option<PlayerSummary> {
+        let data = self.data.read().ok()?;
+        data.get(steamid)
+            .filter(|(_, fetched)| fetched.elapsed() < PROFILE_TTL)
+            .map(|(summary, _)| summary.clone())
+    }
+
+    pub fn insert(&self, summary: PlayerSummary) {
+        if let Ok(mut data) = self.data.write() {
+            data.retain(|_, (_, fetched)| fetched.elapsed() < PROFILE_TTL);
+            data.insert(summary.steamid.clone(), (summary, Instant::now()));
+        }
+    }
+
+    /// Fills in the persona name and avatar of `partner`, from the cache or from Steam.
+    /// Failures are logged and leave the partner unresolved.
+    pub async fn resolve(&self, client: &SteamClient, partner: &mut Partner) {
+        let summary = match self.get(&partner.steamid) {
+            Some(summary) => Some(summary),
+            None => match client
+                .get_player_summaries(std::slice::from_ref(&partner.steamid))
+                .await
+            {
+                Ok(players) => {
+                    let found = players.into_iter().find(|p| p.steamid == partner.steamid);
+                    if let Some(summary) = &found {
+                        self.insert(summary.clone());
+                    }
+                    found
+                }
+                Err(e) => {
+                    warn!("Failed to resolve profile {}: {}", partner.steamid, e);
+                    None
+                }
+            },
+        };
+
+        if let Some(summary) = summary {
+            partner.name = Some(summary.personaname);
+            partner.avatar_url = summary.avatarfull;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_profile_cache_hit() {
+        let cache = ProfileCache::default();
+        assert!(cache.get("76561197960287930").is_none());
+
+        cache.insert(PlayerSummary {
+            steamid: "76561197960287930".to_string(),
+            personaname: "Rabscuttle".to_string(),
+            avatarfull: None,
+        });
+
+        let summary = cache.get("76561197960287930").expect("Profile not cached");
+        assert_eq!(summary.personaname, "Rabscuttle");
+    }
+
+    #[test]
+    fn test_partner_display() {
+        let mut partner = Partner::new("76561197960287930".to_string());
+        assert_eq!(partner.display_name(), "76561197960287930");
+        assert_eq!(
+            partner.profile_url(),
+            "https://steamcommunity.com/profiles/76561197960287930"
+        );
+
+        partner.name = Some("Rabscuttle".to_string());
+        assert_eq!(partner.display_name(), "Rabscuttle");
+    }
+}
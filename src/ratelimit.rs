@@ -0,0 +1,86 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use tokio::time::sleep;
+
+use crate::config::SteamRateLimitConfig;
+
+/// A token bucket shared by every clone. Each request takes a token, and tokens refill at a
+/// steady rate up to the burst size.
+#[derive(Clone)]
+pub struct RateLimiter {
+    bucket: Arc<Mutex<Bucket>>,
+}
+
+struct Bucket {
+    tokens: f64,
+    capacity: f64,
+    per_second: f64,
+    updated: Instant,
+}
+
+impl RateLimiter {
+    pub fn new(config: &SteamRateLimitConfig) -> Self {
+        let capacity = f64::from(config.burst.max(1));
+        Self {
+            bucket: Arc::new(Mutex::new(Bucket {
+                tokens: capacity,
+                capacity,
+                per_second: f64::from(config.requests_per_minute.max(1)) / 60.0,
+                updated: Instant::now(),
+            })),
+        }
+    }
+
+    /// Waits until a request may be made. Callers are let through in the order they arrive.
+    pub async fn acquire(&self) {
+        let wait = self.reserve(Instant::now());
+        if !wait.is_zero() {
+            sleep(wait).await;
+        }
+    }
+
+    /// Takes a token, even if it hasn't refilled yet, and returns how long until it has.
+    fn reserve(&self, now: Instant) -> Duration {
+        let Ok(mut bucket) = self.bucket.lock() else {
+            return Duration::ZERO;
+        };
+        let elapsed = now.saturating_duration_since(bucket.updated).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * bucket.per_second).min(bucket.capacity);
+        bucket.updated = now;
+
+        bucket.tokens -= 1.0;
+        if bucket.tokens >= 0.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(-bucket.tokens / bucket.per_second)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bursts_then_spaces_requests() {
+        let limiter = RateLimiter::new(&SteamRateLimitConfig {
+            requests_per_minute: 60,
+            burst: 2,
+        });
+        let start = Instant::now();
+
+        assert_eq!(limiter.reserve(start), Duration::ZERO);
+        assert_eq!(limiter.reserve(start), Duration::ZERO);
+        // Later callers queue up behind each other, a second apart.
+        assert_eq!(limiter.reserve(start), Duration::from_secs(1));
+        assert_eq!(limiter.reserve(start), Duration::from_secs(2));
+
+        // After a quiet spell the bucket refills, but only up to the burst size.
+        let later = start + Duration::from_secs(60);
+        assert_eq!(limiter.reserve(later), Duration::ZERO);
+        assert_eq!(limiter.reserve(later), Duration::ZERO);
+        assert_eq!(limiter.reserve(later), Duration::from_secs(1));
+    }
+}
@@ -0,0 +1,240 @@
+use anyhow::{Context, Result};
+use regex::Regex;
+
+use crate::{
+    config::{RuleAction, RuleConfig},
+    notifier::{EventItem, TradeEvent},
+};
+
+/// A `RuleConfig` with its patterns compiled.
+#[derive(Debug, Clone)]
+struct Rule {
+    config: RuleConfig,
+    name: Option<Regex>,
+    item_type: Option<Regex>,
+}
+
+impl Rule {
+    fn matches(&self, event: &TradeEvent) -> bool {
+        let c = &self.config;
+
+        let has_item_conditions =
+            !c.appid.is_empty() || self.name.is_some() || self.item_type.is_some();
+        if has_item_conditions {
+            let matched = if c.all_items {
+                !event.items.is_empty() && event.items.iter().all(|i| self.matches_item(i))
+            } else {
+                event.items.iter().any(|i| self.matches_item(i))
+            };
+            if !matched {
+                return false;
+            }
+        }
+
+        if !c.partner.is_empty() {
+            let partner = event.partner.as_ref().map(|p| p.steamid.as_str());
+            if !partner.is_some_and(|p| c.partner.iter().any(|s| s == p)) {
+                return false;
+            }
+        }
+
+        let count = event.items.len();
+        if c.min_items.is_some_and(|min| count < min) || c.max_items.is_some_and(|max| count > max)
+        {
+            return false;
+        }
+
+        if c.min_value.is_some() || c.max_value.is_some() {
+            let Some(valuation) = &event.valuation else {
+                return false;
+            };
+            let value = valuation.total_received().max(valuation.total_given());
+            if c.min_value.is_some_and(|min| value < to_minor_units(min))
+                || c.max_value.is_some_and(|max| value > to_minor_units(max))
+            {
+                return false;
+            }
+        }
+
+        true
+    }
+
+    fn matches_item(&self, item: &EventItem) -> bool {
+        let c = &self.config;
+        if !c.appid.is_empty() && !c.appid.contains(&item.appid) {
+            return false;
+        }
+        if let Some(name) = &self.name
+            && !item
+                .market_hash_name
+                .as_deref()
+                .is_some_and(|n| name.is_match(n))
+        {
+            return false;
+        }
+        if let Some(item_type) = &self.item_type
+            && !item.type_.as_deref().is_some_and(|t| item_type.is_match(t))
+        {
+            return false;
+        }
+        true
+    }
+}
+
+/// The configured filtering rules, in order.
+#[derive(Debug, Clone, Default)]
+pub struct RuleSet {
+    rules: Vec<Rule>,
+}
+
+impl RuleSet {
+    pub fn new(configs: &[RuleConfig]) -> Result<Self> {
+        let mut rules = Vec::new();
+        for (i, config) in configs.iter().enumerate() {
+            let compile = |pattern: &Option<String>, field: &str| {
+                pattern
+                    .as_deref()
+                    .map(Regex::new)
+                    .transpose()
+                    .with_context(|| format!("Invalid regex in rules[{}].{}", i, field))
+            };
+            rules.push(Rule {
+                name: compile(&config.name, "name")?,
+                item_type: compile(&config.item_type, "type")?,
+                config: config.clone(),
+            });
+        }
+        Ok(Self { rules })
+    }
+
+    /// The action of the first rule matching the event; `Include` when none match.
+    pub fn evaluate(&self, event: &TradeEvent) -> RuleAction {
+        self.rules
+            .iter()
+            .find(|rule| rule.matches(event))
+            .map_or(RuleAction::Include, |rule| rule.config.action)
+    }
+}
+
+fn to_minor_units(amount: f64) -> u64 {
+    (amount * 100.0).round().max(0.0) as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        models::TradeOfferState,
+        notifier::TradeEventKind,
+        pricing::{ItemValue, Valuation},
+        profiles::Partner,
+    };
+
+    fn item(appid: u32, name: &str, type_: &str) -> EventItem {
+        EventItem {
+            appid,
+            market_hash_name: Some(name.to_string()),
+            type_: Some(type_.to_string()),
+            received: true,
+        }
+    }
+
+    fn event(items: Vec<EventItem>, value: Option<u64>) -> TradeEvent {
+        let mut event = TradeEvent::new(
+            "Bot1",
+            TradeEventKind::Completed,
+            "1",
+            TradeOfferState::Accepted,
+        );
+        event.partner = Some(Partner::new("76561197960287930".to_string()));
+        event.items = items;
+        event.valuation = value.map(|v| Valuation {
+            currency: 1,
+            received: vec![ItemValue {
+                name: "Key".to_string(),
+                count: 1,
+                unit_price: Some(v),
+            }],
+            given: Vec::new(),
+        });
+        event
+    }
+
+    fn rules(json: &str) -> RuleSet {
+        let configs: Vec<RuleConfig> = serde_json::from_str(json).unwrap();
+        RuleSet::new(&configs).unwrap()
+    }
+
+    #[test]
+    fn test_first_matching_rule_wins() {
+        let rules = rules(
+            r#"[
+                { "min_value": 50, "action": "priority" },
+                { "appid": [440], "name": "^(Refined|Reclaimed|Scrap) Metal$", "all_items": true, "action": "exclude" },
+                { "appid": [730], "action": "include" },
+                { "action": "exclude" }
+            ]"#,
+        );
+
+        let metal = event(
+            vec![
+                item(440, "Refined Metal", "Craft Item"),
+                item(440, "Scrap Metal", "Craft Item"),
+            ],
+            Some(200),
+        );
+        assert_eq!(rules.evaluate(&metal), RuleAction::Exclude);
+
+        let metal_for_hat = event(
+            vec![
+                item(440, "Refined Metal", "Craft Item"),
+                item(440, "Team Captain", "Level 1 Hat"),
+            ],
+            Some(200),
+        );
+        // Not all items are metal, and no later rule includes TF2.
+        assert_eq!(rules.evaluate(&metal_for_hat), RuleAction::Exclude);
+
+        let knife = event(vec![item(730, "★ Karambit", "Covert Knife")], Some(60000));
+        assert_eq!(rules.evaluate(&knife), RuleAction::Priority);
+
+        let case = event(
+            vec![item(730, "Revolution Case", "Base Grade Container")],
+            None,
+        );
+        assert_eq!(rules.evaluate(&case), RuleAction::Include);
+    }
+
+    #[test]
+    fn test_partner_type_and_count_conditions() {
+        let rules = rules(
+            r#"[
+                { "partner": ["76561197960287930"], "max_items": 1, "type": "Hat$", "action": "priority" }
+            ]"#,
+        );
+
+        let hat = event(vec![item(440, "Team Captain", "Level 1 Hat")], None);
+        assert_eq!(rules.evaluate(&hat), RuleAction::Priority);
+
+        let two_hats = event(
+            vec![
+                item(440, "Team Captain", "Level 1 Hat"),
+                item(440, "Towering Pillar of Hats", "Level 5 Hat"),
+            ],
+            None,
+        );
+        assert_eq!(rules.evaluate(&two_hats), RuleAction::Include);
+
+        let mut stranger = hat.clone();
+        stranger.partner = Some(Partner::new("76561197960265728".to_string()));
+        assert_eq!(rules.evaluate(&stranger), RuleAction::Include);
+    }
+
+    #[test]
+    fn test_invalid_regex_is_rejected() {
+        let configs: Vec<RuleConfig> =
+            serde_json::from_str(r#"[{ "name": "(", "action": "exclude" }]"#).unwrap();
+        let error = RuleSet::new(&configs).unwrap_err();
+        assert!(error.to_string().contains("rules[0].name"));
+    }
+}
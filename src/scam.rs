@@ -0,0 +1,199 @@
+use std::collections::BTreeMap;
+
+use crate::models::{TradeOffer, TradeOfferState};
+
+/// How long after a sent offer is canceled a lookalike replacement is still considered suspicious.
+const REDIRECT_WINDOW_SECS: u64 = 15 * 60;
+
+/// A sent offer that was canceled and re-sent with the same items to a different account,
+/// the signature of a stolen Web API key being used to redirect a trade.
+#[derive(Debug, Clone)]
+pub struct Redirect {
+    pub canceled: TradeOffer,
+    pub replacement: TradeOffer,
+}
+
+/// Remembers recently canceled and newly created sent offers so that a cancellation and its
+/// replacement are paired up even when they show up in different polls.
+#[derive(Default)]
+pub struct RedirectDetector {
+    canceled: Vec<TradeOffer>,
+    created: Vec<TradeOffer>,
+}
+
+impl RedirectDetector {
+    /// Records a sent offer whose state just changed from `previous`.
+    pub fn observe(&mut self, offer: &TradeOffer, previous: Option<TradeOfferState>) {
+        match offer.trade_offer_state {
+            TradeOfferState::Canceled | TradeOfferState::CanceledBySecondFactor => {
+                self.canceled.push(offer.clone());
+            }
+            TradeOfferState::Active | TradeOfferState::CreatedNeedsConfirmation
+                if previous.is_none() =>
+            {
+                self.created.push(offer.clone());
+            }
+            _ => {}
+        }
+    }
+
+    /// Returns every newly detected redirect and forgets offers that are too old to match.
+    pub fn take_redirects(&mut self, now: u64) -> Vec<Redirect> {
+        let mut redirects = Vec::new();
+
+        self.created.retain(|replacement| {
+            let matched = self
+                .canceled
+                .iter()
+                .position(|canceled| is_redirect(canceled, replacement));
+            match matched {
+                Some(index) => {
+                    redirects.push(Redirect {
+                        canceled: self.canceled.remove(index),
+                        replacement: replacement.clone(),
+                    });
+                    false
+                }
+                None => true,
+            }
+        });
+
+        let horizon = now.saturating_sub(REDIRECT_WINDOW_SECS);
+        self.canceled.retain(|o| o.time_updated >= horizon);
+        self.created.retain(|o| o.time_created >= horizon);
+
+        redirects
+    }
+}
+
+fn is_redirect(canceled: &TradeOffer, replacement: &TradeOffer) -> bool {
+    if canceled.accountid_other == replacement.accountid_other {
+        return false;
+    }
+
+    // The replacement is created right around the cancellation; allow for either order.
+    if replacement.time_created + REDIRECT_WINDOW_SECS < canceled.time_updated
+        || replacement.time_created > canceled.time_updated + REDIRECT_WINDOW_SECS
+    {
+        return false;
+    }
+
+    let given = item_set(canceled);
+    !given.is_empty() && given == item_set(replacement)
+}
+
+/// The items given away by an offer, as (appid, contextid, assetid) -> amount.
+fn item_set(offer: &TradeOffer) -> BTreeMap<(u32, &str, &str), &str> {
+    offer
+        .items_to_give
+        .iter()
+        .flatten()
+        .map(|a| {
+            (
+                (a.appid, a.contextid.as_str(), a.assetid.as_str()),
+                a.amount.as_str(),
+            )
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::Asset;
+
+    fn offer(
+        id: &str,
+        state: TradeOfferState,
+        partner: i64,
+        time: u64,
+        assetids: &[&str],
+    ) -> TradeOffer {
+        TradeOffer {
+            tradeofferid: id.to_string(),
+            trade_offer_state: state,
+            message: None,
+            time_created: time,
+            time_updated: time,
+            accountid_other: partner,
+            tradeid: None,
+            items_to_give: Some(
+                assetids
+                    .iter()
+                    .map(|assetid| Asset {
+                        appid: 730,
+                        contextid: "2".to_string(),
+                        assetid: assetid.to_string(),
+                        classid: "100".to_string(),
+                        instanceid: "0".to_string(),
+                        amount: "1".to_string(),
+                        new_assetid: None,
+                        new_contextid: None,
+                    })
+                    .collect(),
+            ),
+            items_to_receive: None,
+        }
+    }
+
+    #[test]
+    fn test_detects_redirect_across_polls() {
+        let mut detector = RedirectDetector::default();
+        let now = 1_700_000_000;
+
+        let canceled = offer("1", TradeOfferState::Canceled, 111, now, &["a", "b"]);
+        detector.observe(&canceled, Some(TradeOfferState::Active));
+        assert!(detector.take_redirects(now).is_empty());
+
+        let replacement = offer("2", TradeOfferState::Active, 999, now + 20, &["b", "a"]);
+        detector.observe(&replacement, None);
+        let redirects = detector.take_redirects(now + 30);
+
+        assert_eq!(redirects.len(), 1);
+        assert_eq!(redirects[0].canceled.tradeofferid, "1");
+        assert_eq!(redirects[0].replacement.tradeofferid, "2");
+
+        // Each pair is only reported once.
+        assert!(detector.take_redirects(now + 60).is_empty());
+    }
+
+    #[test]
+    fn test_ignores_same_partner_and_different_items() {
+        let mut detector = RedirectDetector::default();
+        let now = 1_700_000_000;
+
+        detector.observe(
+            &offer("1", TradeOfferState::Canceled, 111, now, &["a"]),
+            Some(TradeOfferState::Active),
+        );
+        // Re-sent to the same partner, e.g. to fix a mistake.
+        detector.observe(&offer("2", TradeOfferState::Active, 111, now, &["a"]), None);
+        // Different items to a different partner.
+        detector.observe(&offer("3", TradeOfferState::Active, 999, now, &["c"]), None);
+
+        assert!(detector.take_redirects(now).is_empty());
+    }
+
+    #[test]
+    fn test_forgets_old_cancellations() {
+        let mut detector = RedirectDetector::default();
+        let now = 1_700_000_000;
+
+        detector.observe(
+            &offer("1", TradeOfferState::Canceled, 111, now, &["a"]),
+            Some(TradeOfferState::Active),
+        );
+        assert!(
+            detector
+                .take_redirects(now + REDIRECT_WINDOW_SECS + 1)
+                .is_empty()
+        );
+
+        let later = now + REDIRECT_WINDOW_SECS + 5;
+        detector.observe(
+            &offer("2", TradeOfferState::Active, 999, later, &["a"]),
+            None,
+        );
+        assert!(detector.take_redirects(later).is_empty());
+    }
+}
@@ -0,0 +1,220 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock},
+};
+
+use crate::{files::write_atomic, models::TradeOfferState};
+
+/// How long processed IDs are remembered after the poll cursor has moved past them.
+const PROCESSED_RETENTION_SECS: u64 = 7 * 24 * 60 * 60;
+/// How long the state of an offer that can still change is remembered. Longer than Steam's
+/// 14 day offer expiry and 15 day escrow hold, so the next change is compared against it.
+const OPEN_OFFER_RETENTION_SECS: u64 = 30 * 24 * 60 * 60;
+
+/// Per-account polling progress that survives restarts.
+#[derive(Debug, Default, Clone, Deserialize, Serialize)]
+pub struct AccountState {
+    /// Cutoff used for the next `GetTradeOffers` poll. Zero means never polled.
+    #[serde(default)]
+    pub last_poll_time: u64,
+    /// Trade offer IDs already handled, mapped to their `time_updated`.
+    #[serde(default)]
+    pub processed_offers: HashMap<String, u64>,
+    /// Trade history IDs already notified, mapped to their `time_init`.
+    #[serde(default)]
+    pub processed_trades: HashMap<String, u64>,
+    /// Last seen state of every offer, keyed by trade offer ID.
+    #[serde(default)]
+    pub offer_states: HashMap<String, OfferRecord>,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize, Serialize)]
+pub struct OfferRecord {
+    pub state: TradeOfferState,
+    pub time_updated: u64,
+}
+
+impl AccountState {
+    /// Processed IDs updated before this may have been pruned, so offers last updated before it
+    /// must not be acted on again.
+    fn horizon(&self) -> u64 {
+        self.last_poll_time.saturating_sub(PROCESSED_RETENTION_SECS)
+    }
+
+    /// The earliest `time_updated` worth acting on: no earlier than `cutoff`, nor than the
+    /// point before which processed IDs are forgotten.
+    pub fn effective_cutoff(&self, cutoff: u64) -> u64 {
+        cutoff.max(self.horizon())
+    }
+
+    /// Forgets processed IDs that are too old to be returned by Steam again.
+    pub fn prune(&mut self) {
+        let horizon = self.horizon();
+        self.processed_offers.retain(|_, time| *time >= horizon);
+        self.processed_trades.retain(|_, time| *time >= horizon);
+        let open_horizon = self
+            .last_poll_time
+            .saturating_sub(OPEN_OFFER_RETENTION_SECS);
+        self.offer_states.retain(|_, record| {
+            let horizon = if record.state.is_open() {
+                open_horizon
+            } else {
+                horizon
+            };
+            record.time_updated >= horizon
+        });
+    }
+}
+
+#[derive(Clone)]
+pub struct StateStore {
+    data: Arc<RwLock<HashMap<String, AccountState>>>,
+    file_path: PathBuf,
+}
+
+impl StateStore {
+    pub fn new<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let file_path = path.as_ref().to_path_buf();
+        let mut data = HashMap::new();
+
+        if file_path.exists() {
+            let content = fs::read_to_string(&file_path)
+                .with_context(|| format!("Failed to read state file: {:?}", file_path))?;
+            if !content.is_empty() {
+                data = serde_json::from_str(&content)
+                    .with_context(|| format!("Failed to parse state file: {:?}", file_path))?;
+            }
+        }
+
+        Ok(Self {
+            data: Arc::new(RwLock::new(data)),
+            file_path,
+        })
+    }
+
+    pub fn get(&self, account: &str) -> AccountState {
+        self.data
+            .read()
+            .ok()
+            .and_then(|data| data.get(account).cloned())
+            .unwrap_or_default()
+    }
+
+    pub fn set(&self, account: &str, state: AccountState) -> Result<()> {
+        // Saving under the write lock keeps pollers from overwriting each other's newer state.
+        let mut data = self
+            .data
+            .write()
+            .map_err(|_| anyhow::anyhow!("Lock poisoned"))?;
+        data.insert(account.to_string(), state);
+
+        self.save(&data)
+    }
+
+    fn save(&self, data: &HashMap<String, AccountState>) -> Result<()> {
+        let content = serde_json::to_string_pretty(data)?;
+        write_atomic(&self.file_path, &content, "state")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    fn get_temp_file_path() -> PathBuf {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_nanos();
+        let mut path = std::env::temp_dir();
+        path.push(format!("test_state_{}.json", now));
+        path
+    }
+
+    #[test]
+    fn test_state_persistence() {
+        let path = get_temp_file_path();
+
+        {
+            let store = StateStore::new(&path).expect("Failed to create state store");
+            assert_eq!(store.get("Bot1").last_poll_time, 0);
+
+            let mut state = AccountState {
+                last_poll_time: 1600000000,
+                ..Default::default()
+            };
+            state.processed_offers.insert("111".to_string(), 1600000000);
+            state.processed_trades.insert("222".to_string(), 1600000000);
+            store.set("Bot1", state).expect("Failed to save state");
+        }
+
+        {
+            let store = StateStore::new(&path).expect("Failed to load state store");
+            let state = store.get("Bot1");
+            assert_eq!(state.last_poll_time, 1600000000);
+            assert!(state.processed_offers.contains_key("111"));
+            assert!(state.processed_trades.contains_key("222"));
+            assert_eq!(store.get("Bot2").last_poll_time, 0);
+        }
+
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_prune_drops_old_ids() {
+        let now = 1_700_000_000;
+        let mut state = AccountState {
+            last_poll_time: now,
+            ..Default::default()
+        };
+        state.processed_offers.insert("old".to_string(), 1_000);
+        state.processed_offers.insert("new".to_string(), now - 10);
+        state.processed_trades.insert("old".to_string(), 1_000);
+        for (id, offer_state) in [
+            ("escrow", TradeOfferState::InEscrow),
+            ("declined", TradeOfferState::Declined),
+        ] {
+            state.offer_states.insert(
+                id.to_string(),
+                OfferRecord {
+                    state: offer_state,
+                    time_updated: now - 10 * 24 * 60 * 60,
+                },
+            );
+        }
+
+        state.prune();
+
+        assert!(!state.processed_offers.contains_key("old"));
+        assert!(state.processed_offers.contains_key("new"));
+        assert!(state.processed_trades.is_empty());
+        // Offers still in escrow keep their state until the hold ends.
+        assert!(state.offer_states.contains_key("escrow"));
+        assert!(!state.offer_states.contains_key("declined"));
+    }
+
+    #[test]
+    fn test_pruned_offers_are_not_replayed() {
+        let start = 1_700_000_000;
+        let mut state = AccountState {
+            last_poll_time: start,
+            ..Default::default()
+        };
+        state
+            .processed_offers
+            .insert("accepted".to_string(), start + 60);
+        assert!(start + 60 >= state.effective_cutoff(start));
+
+        // Eight days on, the processed ID is forgotten, but Steam may still return the offer.
+        state.last_poll_time = start + 8 * 24 * 60 * 60;
+        state.prune();
+
+        assert!(!state.processed_offers.contains_key("accepted"));
+        assert!(start + 60 < state.effective_cutoff(start));
+    }
+}
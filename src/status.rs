@@ -0,0 +1,111 @@
+use chrono::Utc;
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, RwLock},
+};
+
+/// Live health of one account's poller.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AccountStatus {
+    /// Unix time of the last successful poll.
+    pub last_poll: Option<u64>,
+    /// Unix time and message of the most recent failed poll.
+    pub last_error: Option<(u64, String)>,
+    /// Paused accounts are not polled until resumed. Not persisted across restarts.
+    pub paused: bool,
+}
+
+/// Shared view of every poller, updated by the pollers and read by bot commands.
+#[derive(Clone, Default)]
+pub struct StatusRegistry {
+    accounts: Arc<RwLock<BTreeMap<String, AccountStatus>>>,
+}
+
+impl StatusRegistry {
+    pub fn register(&self, account: &str) {
+        if let Ok(mut accounts) = self.accounts.write() {
+            accounts.entry(account.to_string()).or_default();
+        }
+    }
+
+    /// Forgets an account whose poller was stopped.
+    pub fn unregister(&self, account: &str) {
+        if let Ok(mut accounts) = self.accounts.write() {
+            accounts.remove(account);
+        }
+    }
+
+    pub fn record_success(&self, account: &str) {
+        if let Ok(mut accounts) = self.accounts.write() {
+            accounts.entry(account.to_string()).or_default().last_poll =
+                Some(Utc::now().timestamp() as u64);
+        }
+    }
+
+    pub fn record_error(&self, account: &str, error: String) {
+        if let Ok(mut accounts) = self.accounts.write() {
+            accounts.entry(account.to_string()).or_default().last_error =
+                Some((Utc::now().timestamp() as u64, error));
+        }
+    }
+
+    pub fn is_paused(&self, account: &str) -> bool {
+        self.accounts
+            .read()
+            .ok()
+            .and_then(|accounts| accounts.get(account).map(|s| s.paused))
+            .unwrap_or(false)
+    }
+
+    /// Pauses or resumes an account, matching its name case-insensitively.
+    /// Returns the account's canonical name, or `None` if there is no such account.
+    pub fn set_paused(&self, account: &str, paused: bool) -> Option<String> {
+        let mut accounts = self.accounts.write().ok()?;
+        let (name, status) = accounts
+            .iter_mut()
+            .find(|(name, _)| name.eq_ignore_ascii_case(account))?;
+        status.paused = paused;
+        Some(name.clone())
+    }
+
+    pub fn snapshot(&self) -> BTreeMap<String, AccountStatus> {
+        self.accounts
+            .read()
+            .map(|accounts| accounts.clone())
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pause_and_resume() {
+        let registry = StatusRegistry::default();
+        registry.register("Main Account");
+
+        assert!(!registry.is_paused("Main Account"));
+        assert_eq!(
+            registry.set_paused("main account", true),
+            Some("Main Account".to_string())
+        );
+        assert!(registry.is_paused("Main Account"));
+
+        registry.set_paused("MAIN ACCOUNT", false);
+        assert!(!registry.is_paused("Main Account"));
+
+        assert_eq!(registry.set_paused("Nobody", true), None);
+    }
+
+    #[test]
+    fn test_records_poll_results() {
+        let registry = StatusRegistry::default();
+        registry.record_success("Bot1");
+        registry.record_error("Bot1", "Steam API error".to_string());
+
+        let status = &registry.snapshot()["Bot1"];
+        assert!(status.last_poll.is_some());
+        assert_eq!(status.last_error.as_ref().unwrap().1, "Steam API error");
+    }
+}
@@ -3,18 +3,24 @@ use reqwest::Client;
 use serde::Deserialize;
 use std::collections::HashMap;
 
-use crate::models::*;
+use crate::{models::*, ratelimit::RateLimiter};
+
+const TRADE_HISTORY_PAGE_SIZE: u32 = 30;
+const INVENTORY_PAGE_SIZE: u32 = 2000;
 
 pub struct SteamClient {
     api_key: String,
     client: Client,
+    /// Shared with the other accounts' clients, so together they stay under Steam's limits.
+    limiter: RateLimiter,
 }
 
 impl SteamClient {
-    pub fn new(api_key: String) -> Self {
+    pub fn new(api_key: String, limiter: RateLimiter) -> Self {
         Self {
             api_key,
             client: Client::new(),
+            limiter,
         }
     }
 
@@ -24,6 +30,7 @@ impl SteamClient {
     ) -> Result<GetTradeOffersResponse> {
         let url = "https://api.steampowered.com/IEconService/GetTradeOffers/v1/";
 
+        self.limiter.acquire().await;
         let response = self
             .client
             .get(url)
@@ -41,6 +48,7 @@ impl SteamClient {
             ])
             .send()
             .await
+            .map_err(reqwest::Error::without_url)
             .context("Failed to fetch trade offers")?;
 
         if !response.status().is_success() {
@@ -54,26 +62,39 @@ impl SteamClient {
         let result = response
             .json::<GetTradeOffersResponse>()
             .await
+            .map_err(reqwest::Error::without_url)
             .context("Failed to parse trade offers response")?;
 
         Ok(result)
     }
 
-    pub async fn get_trade_history(&self, start_time: u64) -> Result<GetTradeHistoryResponse> {
+    /// Fetches one page of trade history, newest first. Pass the `time_init` and `tradeid`
+    /// of the last trade on the previous page to continue paging into older trades.
+    pub async fn get_trade_history(
+        &self,
+        start_after: Option<(u64, &str)>,
+    ) -> Result<GetTradeHistoryResponse> {
         let url = "https://api.steampowered.com/IEconService/GetTradeHistory/v1/";
 
+        let mut params: Vec<(&str, String)> = vec![
+            ("key", self.api_key.clone()),
+            ("max_trades", TRADE_HISTORY_PAGE_SIZE.to_string()),
+            ("get_descriptions", "0".to_string()),
+            ("format", "json".to_string()),
+        ];
+        if let Some((time, tradeid)) = start_after {
+            params.push(("start_after_time", time.to_string()));
+            params.push(("start_after_tradeid", tradeid.to_string()));
+        }
+
+        self.limiter.acquire().await;
         let response = self
             .client
             .get(url)
-            .query(&[
-                ("key", &self.api_key),
-                ("max_trades", &"10".to_string()),
-                ("start_time", &start_time.to_string()),
-                ("get_descriptions", &"0".to_string()),
-                ("format", &"json".to_string()),
-            ])
+            .query(&params)
             .send()
             .await
+            .map_err(reqwest::Error::without_url)
             .context("Failed to fetch trade history")?;
 
         if !response.status().is_success() {
@@ -87,6 +108,7 @@ impl SteamClient {
         let text = response
             .text()
             .await
+            .map_err(reqwest::Error::without_url)
             .context("Failed to get response text")?;
 
         // Parse the response, logging the raw body if it fails.
@@ -96,6 +118,189 @@ impl SteamClient {
         Ok(result)
     }
 
+    /// Pages through trade history until reaching trades older than `since`.
+    /// Returns the matching trades newest first.
+    pub async fn get_trade_history_since(&self, since: u64) -> Result<Vec<TradeHistory>> {
+        let mut trades = Vec::new();
+        let mut start_after: Option<(u64, String)> = None;
+
+        loop {
+            let page = self
+                .get_trade_history(start_after.as_ref().map(|(t, id)| (*t, id.as_str())))
+                .await?
+                .response;
+
+            let more = page.more.unwrap_or(false);
+            let Some(last) = page.trades.last() else {
+                break;
+            };
+            start_after = Some((last.time_init, last.tradeid.clone()));
+
+            let reached_end = last.time_init < since;
+            trades.extend(page.trades.into_iter().filter(|t| t.time_init >= since));
+
+            if reached_end || !more {
+                break;
+            }
+        }
+
+        Ok(trades)
+    }
+
+    /// Pages back through trade history looking for `tradeid`, stopping once trades are older
+    /// than `not_before`.
+    pub async fn find_trade_by_id(
+        &self,
+        tradeid: &str,
+        not_before: u64,
+    ) -> Result<Option<TradeHistory>> {
+        let mut start_after: Option<(u64, String)> = None;
+
+        loop {
+            let page = self
+                .get_trade_history(start_after.as_ref().map(|(t, id)| (*t, id.as_str())))
+                .await?
+                .response;
+
+            let more = page.more.unwrap_or(false);
+            let Some(last) = page.trades.last() else {
+                return Ok(None);
+            };
+            start_after = Some((last.time_init, last.tradeid.clone()));
+            let reached_end = last.time_init < not_before;
+
+            if let Some(found) = page.trades.into_iter().find(|t| t.tradeid == tradeid) {
+                return Ok(Some(found));
+            }
+            if reached_end || !more {
+                return Ok(None);
+            }
+        }
+    }
+
+    /// Makes a cheap authenticated request, to check that Steam accepts the API key.
+    pub async fn check_api_key(&self) -> Result<()> {
+        let url = "https://api.steampowered.com/IEconService/GetTradeOffersSummary/v1/";
+
+        self.limiter.acquire().await;
+        let response = self
+            .client
+            .get(url)
+            .query(&[
+                ("key", self.api_key.as_str()),
+                ("time_last_visit", "0"),
+                ("format", "json"),
+            ])
+            .send()
+            .await
+            .map_err(reqwest::Error::without_url)
+            .context("Failed to reach the Steam API")?;
+
+        let status = response.status();
+        if status == reqwest::StatusCode::FORBIDDEN || status == reqwest::StatusCode::UNAUTHORIZED {
+            anyhow::bail!("Steam rejected the API key ({})", status);
+        }
+        if !status.is_success() {
+            anyhow::bail!("Steam API error (GetTradeOffersSummary): {}", status);
+        }
+        Ok(())
+    }
+
+    pub async fn get_player_summaries(&self, steamids: &[String]) -> Result<Vec<PlayerSummary>> {
+        if steamids.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let url = "https://api.steampowered.com/ISteamUser/GetPlayerSummaries/v2/";
+
+        self.limiter.acquire().await;
+        let response = self
+            .client
+            .get(url)
+            .query(&[
+                ("key", self.api_key.as_str()),
+                ("steamids", &steamids.join(",")),
+                ("format", "json"),
+            ])
+            .send()
+            .await
+            .map_err(reqwest::Error::without_url)
+            .context("Failed to fetch player summaries")?;
+
+        if !response.status().is_success() {
+            let error_text = response
+                .text()
+                .await
+                .unwrap_or_else(|_| "Unknown error".to_string());
+            anyhow::bail!("Steam API error (GetPlayerSummaries): {}", error_text);
+        }
+
+        let result = response
+            .json::<GetPlayerSummariesResponse>()
+            .await
+            .map_err(reqwest::Error::without_url)
+            .context("Failed to parse player summaries response")?;
+
+        Ok(result.response.players)
+    }
+
+    /// Fetches the descriptions of every item in a public inventory, from the Steam Community
+    /// rather than the Web API.
+    pub async fn get_inventory(
+        &self,
+        steamid: &str,
+        appid: u32,
+        contextid: &str,
+    ) -> Result<Vec<InventoryDescription>> {
+        let url = format!(
+            "https://steamcommunity.com/inventory/{}/{}/{}",
+            steamid, appid, contextid
+        );
+        let mut descriptions = Vec::new();
+        let mut start_assetid: Option<String> = None;
+
+        loop {
+            let mut params = vec![
+                ("l", "english".to_string()),
+                ("count", INVENTORY_PAGE_SIZE.to_string()),
+            ];
+            if let Some(start) = &start_assetid {
+                params.push(("start_assetid", start.clone()));
+            }
+
+            self.limiter.acquire().await;
+            let response = self
+                .client
+                .get(&url)
+                .query(&params)
+                .send()
+                .await
+                .map_err(reqwest::Error::without_url)
+                .context("Failed to fetch inventory")?;
+
+            if !response.status().is_success() {
+                anyhow::bail!(
+                    "Steam Community error (inventory): {}. Is the inventory public?",
+                    response.status()
+                );
+            }
+
+            let page = response
+                .json::<GetInventoryResponse>()
+                .await
+                .map_err(reqwest::Error::without_url)
+                .context("Failed to parse inventory response")?;
+
+            descriptions.extend(page.descriptions);
+            match page.last_assetid {
+                Some(last) if page.more_items == Some(1) => start_assetid = Some(last),
+                _ => break,
+            }
+        }
+
+        Ok(descriptions)
+    }
+
     pub async fn get_asset_class_info(
         &self,
         appid: u32,
@@ -125,12 +330,14 @@ impl SteamClient {
         // Use ISteamEconomy with explicit appid parameter.
         params.push(("appid".to_string(), appid.to_string()));
 
+        self.limiter.acquire().await;
         let response = self
             .client
             .get(url)
             .query(&params)
             .send()
             .await
+            .map_err(reqwest::Error::without_url)
             .context("Failed to fetch asset class info")?;
 
         if !response.status().is_success() {
@@ -150,6 +357,7 @@ impl SteamClient {
         let raw: RawResponse = response
             .json()
             .await
+            .map_err(reqwest::Error::without_url)
             .context("Failed to parse raw asset info")?;
 
         let mut final_map = HashMap::new();
@@ -166,3 +374,15 @@ impl SteamClient {
         Ok(final_map)
     }
 }
+
+/// Finds an item in a `get_asset_class_info` result. Steam keys the result by
+/// "classid_instanceid", or by the class ID alone when the instance is 0.
+pub fn find_class_info<'a>(
+    infos: &'a HashMap<String, AssetClassInfo>,
+    classid: &str,
+    instanceid: &str,
+) -> Option<&'a AssetClassInfo> {
+    infos
+        .get(&format!("{}_{}", classid, instanceid))
+        .or_else(|| infos.get(classid))
+}
@@ -0,0 +1,259 @@
+use anyhow::Result;
+use log::{error, info, warn};
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+use tokio::{sync::watch, task::JoinHandle, time::sleep};
+
+use crate::{
+    cache::ItemCache,
+    cli::Cli,
+    config::{AccountConfig, Config},
+    ledger::Ledger,
+    notifier,
+    outbox::Outbox,
+    poller::{Poller, PollerSettings},
+    pricing::PriceClient,
+    profiles::ProfileCache,
+    ratelimit::RateLimiter,
+    rules::RuleSet,
+    state::StateStore,
+    status::StatusRegistry,
+    steam::SteamClient,
+    telegram::TelegramBot,
+};
+
+/// How often the config file is checked for changes.
+const RELOAD_CHECK_INTERVAL: Duration = Duration::from_secs(5);
+
+/// State shared by every poller, fixed for the life of the process.
+pub struct Shared {
+    pub cache: Arc<ItemCache>,
+    pub state_store: StateStore,
+    pub ledger: Ledger,
+    pub profiles: ProfileCache,
+    pub pricer: Option<PriceClient>,
+    pub status: StatusRegistry,
+    pub telegram: Option<Arc<TelegramBot>>,
+    /// Limits the Steam requests of every account together.
+    pub steam_limiter: RateLimiter,
+    /// API key the item cache is refreshed with: the first account's, kept current on reload.
+    pub refresh_key: watch::Sender<Option<String>>,
+}
+
+impl Shared {
+    pub fn new(cli: &Cli, config: &Config) -> Result<Self> {
+        fs::create_dir_all(&cli.data_dir)?;
+
+        Ok(Self {
+            cache: Arc::new(ItemCache::new(cli.cache_path(), &config.item_cache)?),
+            state_store: StateStore::new(cli.data_path("state.json"))?,
+            ledger: Ledger::open(cli.data_path("trades.db"))?,
+            profiles: ProfileCache::default(),
+            pricer: config.pricing.as_ref().map(PriceClient::new),
+            status: StatusRegistry::default(),
+            telegram: telegram_bot(cli, config)?,
+            steam_limiter: RateLimiter::new(&config.steam_rate_limit),
+            refresh_key: watch::Sender::new(None),
+        })
+    }
+
+    /// Builds the settings of an account's poller from the config.
+    pub fn settings(
+        &self,
+        account: &AccountConfig,
+        config: &Config,
+        rules: &RuleSet,
+    ) -> Result<PollerSettings> {
+        // Each account routes notifications to its own destinations, or the global notifier.
+        let notifier = notifier::for_account(account, config, self.telegram.as_ref())?;
+
+        Ok(PollerSettings {
+            polling_interval: Duration::from_secs(config.polling_interval_seconds),
+            notifier,
+            rules: rules.clone(),
+            notify_incoming_offers: config.notify_incoming_offers,
+            state_notifications: config.state_notifications.clone(),
+            detect_trade_redirects: config.detect_trade_redirects,
+        })
+    }
+
+    pub fn poller(
+        &self,
+        account: &AccountConfig,
+        settings: watch::Receiver<Arc<PollerSettings>>,
+        stop: watch::Receiver<bool>,
+    ) -> Poller {
+        Poller {
+            account_name: account.name.clone(),
+            client: SteamClient::new(account.api_key.clone(), self.steam_limiter.clone()),
+            cache: self.cache.clone(),
+            profiles: self.profiles.clone(),
+            pricer: self.pricer.clone(),
+            state_store: self.state_store.clone(),
+            ledger: self.ledger.clone(),
+            status: self.status.clone(),
+            settings,
+            stop,
+        }
+    }
+}
+
+/// The shared Telegram bot and its outbox, when `telegram_token` is configured.
+/// Messages are only delivered once `run_sender` is spawned.
+pub fn telegram_bot(cli: &Cli, config: &Config) -> Result<Option<Arc<TelegramBot>>> {
+    if config.telegram_token.is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(Arc::new(TelegramBot::new(
+        config.telegram_token.clone(),
+        config.telegram_chat_id.clone(),
+        Outbox::new(cli.data_path("outbox.json"))?,
+    ))))
+}
+
+struct RunningPoller {
+    api_key: String,
+    settings: watch::Sender<Arc<PollerSettings>>,
+    stop: watch::Sender<bool>,
+    handle: JoinHandle<()>,
+}
+
+impl RunningPoller {
+    /// Asks the poller to stop, and waits for it to finish its current poll. Aborting it
+    /// instead could lose the state of a poll whose notifications were already sent.
+    async fn stop(self, account: &str) {
+        self.stop.send_replace(true);
+        if let Err(e) = self.handle.await {
+            error!("[{}] Poller failed: {}", account, e);
+        }
+    }
+}
+
+/// Runs a poller for every configured account, and keeps them in step with the config file.
+pub struct Supervisor {
+    shared: Shared,
+    /// The config last applied.
+    config: Option<Config>,
+    pollers: HashMap<String, RunningPoller>,
+}
+
+impl Supervisor {
+    pub fn new(shared: Shared) -> Self {
+        Self {
+            shared,
+            config: None,
+            pollers: HashMap::new(),
+        }
+    }
+
+    /// Starts, updates and stops pollers to match `config`. If any account's settings can't be
+    /// built, nothing is changed.
+    pub async fn apply(&mut self, config: Config) -> Result<()> {
+        let rules = RuleSet::new(&config.rules)?;
+        let mut accounts = Vec::new();
+        for account in &config.accounts {
+            let settings = self.shared.settings(account, &config, &rules)?;
+            accounts.push((account, Arc::new(settings)));
+        }
+
+        if let Some(previous) = &self.config
+            && needs_restart(previous, &config)
+        {
+            warn!(
+                "Changes to telegram_token, telegram_chat_id, telegram_commands, pricing, \
+                 item_cache or steam_rate_limit take effect after a restart."
+            );
+        }
+
+        let removed: Vec<String> = self
+            .pollers
+            .keys()
+            .filter(|name| !config.accounts.iter().any(|a| &a.name == *name))
+            .cloned()
+            .collect();
+        for name in removed {
+            info!("[{}] Removed from the config, stopping poller.", name);
+            if let Some(running) = self.pollers.remove(&name) {
+                running.stop(&name).await;
+            }
+            self.shared.status.unregister(&name);
+        }
+
+        for (account, settings) in accounts {
+            if let Some(running) = self.pollers.get(&account.name) {
+                if running.api_key == account.api_key {
+                    running.settings.send_replace(settings);
+                    continue;
+                }
+                info!("[{}] API key changed, restarting poller.", account.name);
+                if let Some(running) = self.pollers.remove(&account.name) {
+                    running.stop(&account.name).await;
+                }
+            }
+
+            let (settings_sender, settings) = watch::channel(settings);
+            let (stop_sender, stop) = watch::channel(false);
+            let poller = self.shared.poller(account, settings, stop);
+            self.pollers.insert(
+                account.name.clone(),
+                RunningPoller {
+                    api_key: account.api_key.clone(),
+                    settings: settings_sender,
+                    stop: stop_sender,
+                    handle: tokio::spawn(poller.run()),
+                },
+            );
+        }
+
+        self.shared
+            .refresh_key
+            .send_replace(config.accounts.first().map(|a| a.api_key.clone()));
+        self.config = Some(config);
+        Ok(())
+    }
+
+    /// Reloads the config whenever the file changes, forever. A config that fails to load is
+    /// logged and ignored, leaving the previous one in effect.
+    pub async fn watch_config(mut self, path: PathBuf) {
+        let mut last_modified = modified(&path);
+
+        loop {
+            sleep(RELOAD_CHECK_INTERVAL).await;
+
+            let current = modified(&path);
+            if current == last_modified {
+                continue;
+            }
+            last_modified = current;
+
+            info!("{} changed, reloading...", path.display());
+            let result = match Config::load(&path) {
+                Ok(config) => self.apply(config).await,
+                Err(e) => Err(e),
+            };
+            match result {
+                Ok(()) => info!("Config reloaded, watching {} accounts.", self.pollers.len()),
+                Err(e) => error!("Failed to reload config, keeping the previous one: {:#}", e),
+            }
+        }
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+/// Whether settings that are only read at startup differ.
+fn needs_restart(previous: &Config, current: &Config) -> bool {
+    previous.telegram_token != current.telegram_token
+        || previous.telegram_chat_id != current.telegram_chat_id
+        || previous.telegram_commands != current.telegram_commands
+        || previous.pricing != current.pricing
+        || previous.item_cache != current.item_cache
+        || previous.steam_rate_limit != current.steam_rate_limit
+}
@@ -1,30 +1,317 @@
 use anyhow::{Context, Result};
-use reqwest::Client;
+use async_trait::async_trait;
+use log::{error, warn};
+use reqwest::{Client, StatusCode};
+use serde::Deserialize;
 use serde_json::json;
+use std::{sync::Arc, time::Duration};
+use tokio::time::sleep;
+
+use crate::{
+    notifier::{Notifier, Priority, TradeEvent, TradeEventKind},
+    outbox::{OutboundMessage, Outbox},
+    template::Template,
+};
+
+/// Pause between consecutive messages; Telegram allows roughly one message per second per chat.
+const SEND_INTERVAL: Duration = Duration::from_secs(1);
+const INITIAL_BACKOFF: Duration = Duration::from_secs(2);
+const MAX_BACKOFF: Duration = Duration::from_secs(300);
+/// Used when a 429 response doesn't say how long to wait.
+const DEFAULT_RETRY_AFTER: Duration = Duration::from_secs(5);
+/// Telegram rejects messages longer than this many UTF-16 code units.
+const MAX_MESSAGE_LEN: usize = 4096;
+/// Room left on the first line of each part for a label like " (12/34)".
+const PART_LABEL_RESERVE: usize = 12;
+/// A header that would leave less room than this for the body of each part is not repeated.
+const MIN_BODY_LEN: usize = 1024;
+/// The first line of a template longer than this isn't used as a header.
+const MAX_TEMPLATE_HEADER_LEN: usize = 256;
+/// Put in front of the header of high priority messages.
+const HIGH_PRIORITY_MARKER: &str = "⚠️";
 
 #[derive(Clone)]
 pub struct TelegramBot {
     token: String,
+    /// `telegram_chat_id`; bot commands are only accepted from here.
     chat_id: String,
     client: Client,
+    outbox: Outbox,
+}
+
+/// A chat, and optionally a forum topic within it, that messages are sent to.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChatTarget {
+    pub chat_id: String,
+    pub thread_id: Option<i64>,
+    /// Deliver without a notification sound.
+    pub silent: bool,
+}
+
+/// Why a message could not be delivered, and so what to do about it.
+enum SendError {
+    /// Flood limit hit; Telegram says when to try again.
+    RetryAfter(Duration),
+    /// Network failure or server error; worth retrying.
+    Transient(anyhow::Error),
+    /// The request itself was rejected; retrying won't help.
+    Permanent(anyhow::Error),
+}
+
+#[derive(Debug, Deserialize)]
+struct ErrorResponse {
+    description: Option<String>,
+    parameters: Option<ResponseParameters>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ResponseParameters {
+    retry_after: Option<u64>,
 }
 
 impl TelegramBot {
-    pub fn new(token: String, chat_id: String) -> Self {
+    pub fn new(token: String, chat_id: String, outbox: Outbox) -> Self {
         Self {
             token,
             chat_id,
             client: Client::new(),
+            outbox,
+        }
+    }
+
+    /// Queues a message for the configured chat, split into parts if it is too long.
+    /// It is delivered by `run_sender`, and kept on disk until it has been.
+    pub fn send_notification(&self, message: &str) -> Result<()> {
+        let target = ChatTarget {
+            chat_id: self.chat_id.clone(),
+            thread_id: None,
+            silent: false,
+        };
+        self.send_to(&target, None, message, Priority::Normal)
+    }
+
+    /// Queues a message for `target`, repeating `header`, if any, at the top of every part.
+    pub fn send_to(
+        &self,
+        target: &ChatTarget,
+        header: Option<&str>,
+        body: &str,
+        priority: Priority,
+    ) -> Result<()> {
+        self.outbox.extend(
+            split_message(header, body)
+                .into_iter()
+                .map(|text| OutboundMessage {
+                    chat_id: target.chat_id.clone(),
+                    thread_id: target.thread_id,
+                    silent: target.silent,
+                    high_priority: priority == Priority::High,
+                    text,
+                })
+                .collect(),
+        )
+    }
+
+    /// Delivers queued messages one at a time, forever. Run exactly one of these per outbox
+    /// so that all accounts share a single send rate.
+    pub async fn run_sender(self: Arc<Self>) {
+        let mut backoff = INITIAL_BACKOFF;
+
+        loop {
+            let Some(message) = self.outbox.front() else {
+                self.outbox.wait().await;
+                continue;
+            };
+
+            match self.deliver(&message).await {
+                Ok(()) => {
+                    backoff = INITIAL_BACKOFF;
+                    if let Err(e) = self.outbox.pop_front() {
+                        error!("Failed to update Telegram outbox: {}", e);
+                    }
+                    sleep(SEND_INTERVAL).await;
+                }
+                Err(SendError::RetryAfter(delay)) => {
+                    warn!(
+                        "Telegram rate limit hit, retrying in {}s ({} messages queued)",
+                        delay.as_secs(),
+                        self.outbox.len()
+                    );
+                    sleep(delay).await;
+                }
+                Err(SendError::Transient(e)) => {
+                    warn!(
+                        "Failed to send Telegram message, retrying in {}s: {}",
+                        backoff.as_secs(),
+                        e
+                    );
+                    sleep(backoff).await;
+                    backoff = (backoff * 2).min(MAX_BACKOFF);
+                }
+                Err(SendError::Permanent(e)) => {
+                    error!("Dropping undeliverable Telegram message: {}", e);
+                    backoff = INITIAL_BACKOFF;
+                    if let Err(e) = self.outbox.pop_front() {
+                        error!("Failed to update Telegram outbox: {}", e);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Waits for `run_sender` to deliver every queued message, up to `timeout`.
+    /// Returns whether the outbox was emptied.
+    pub async fn flush(&self, timeout: Duration) -> bool {
+        let start = tokio::time::Instant::now();
+        while !self.outbox.is_empty() {
+            if start.elapsed() >= timeout {
+                return false;
+            }
+            sleep(Duration::from_millis(200)).await;
         }
+        true
     }
 
-    pub async fn send_notification(&self, message: &str) -> Result<()> {
+    async fn deliver(&self, message: &OutboundMessage) -> Result<(), SendError> {
         let url = format!("https://api.telegram.org/bot{}/sendMessage", self.token);
 
+        let mut payload = json!({
+            "chat_id": message.chat_id,
+            "text": message.text,
+            "parse_mode": "HTML",
+            "disable_notification": message.silent && !message.high_priority
+        });
+        if let Some(thread_id) = message.thread_id {
+            payload["message_thread_id"] = json!(thread_id);
+        }
+
+        let response = self
+            .client
+            .post(&url)
+            .json(&payload)
+            .send()
+            .await
+            .map_err(reqwest::Error::without_url)
+            .context("Failed to send Telegram request")
+            .map_err(SendError::Transient)?;
+
+        let status = response.status();
+        if status.is_success() {
+            return Ok(());
+        }
+
+        let error_text = response
+            .text()
+            .await
+            .unwrap_or_else(|_| "Unknown error".to_string());
+        Err(classify_error(status, &error_text))
+    }
+}
+
+fn classify_error(status: StatusCode, body: &str) -> SendError {
+    let parsed = serde_json::from_str::<ErrorResponse>(body).ok();
+    let description = parsed
+        .as_ref()
+        .and_then(|r| r.description.clone())
+        .unwrap_or_else(|| body.to_string());
+
+    if status == StatusCode::TOO_MANY_REQUESTS {
+        let delay = parsed
+            .and_then(|r| r.parameters)
+            .and_then(|p| p.retry_after)
+            .map_or(DEFAULT_RETRY_AFTER, Duration::from_secs);
+        return SendError::RetryAfter(delay);
+    }
+
+    let error = anyhow::anyhow!("Telegram API error ({}): {}", status, description);
+    if status.is_server_error() {
+        SendError::Transient(error)
+    } else {
+        SendError::Permanent(error)
+    }
+}
+
+// --- getUpdates ---
+
+#[derive(Debug, Deserialize)]
+struct GetUpdatesResponse {
+    ok: bool,
+    #[serde(default)]
+    result: Vec<Update>,
+    description: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct GetMeResponse {
+    ok: bool,
+    result: Option<BotUser>,
+    description: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct BotUser {
+    username: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Update {
+    pub update_id: i64,
+    pub message: Option<Message>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Message {
+    pub chat: Chat,
+    pub text: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Chat {
+    pub id: i64,
+}
+
+impl TelegramBot {
+    pub fn chat_id(&self) -> &str {
+        &self.chat_id
+    }
+
+    /// Checks the token with `getMe`, returning the bot's username.
+    pub async fn get_me(&self) -> Result<String> {
+        let url = format!("https://api.telegram.org/bot{}/getMe", self.token);
+
+        let result = self
+            .client
+            .get(&url)
+            .send()
+            .await
+            .map_err(reqwest::Error::without_url)
+            .context("Failed to send Telegram getMe request")?
+            .json::<GetMeResponse>()
+            .await
+            .map_err(reqwest::Error::without_url)
+            .context("Failed to parse Telegram getMe response")?;
+
+        if !result.ok {
+            anyhow::bail!(
+                "Telegram API error: {}",
+                result.description.unwrap_or_default()
+            );
+        }
+
+        Ok(result
+            .result
+            .and_then(|user| user.username)
+            .unwrap_or_default())
+    }
+
+    /// Long-polls for new messages sent to the bot, waiting up to `timeout_secs` for one to arrive.
+    pub async fn get_updates(&self, offset: i64, timeout_secs: u64) -> Result<Vec<Update>> {
+        let url = format!("https://api.telegram.org/bot{}/getUpdates", self.token);
+
         let payload = json!({
-            "chat_id": self.chat_id,
-            "text": message,
-            "parse_mode": "HTML"
+            "offset": offset,
+            "timeout": timeout_secs,
+            "allowed_updates": ["message"]
         });
 
         let response = self
@@ -33,16 +320,443 @@ impl TelegramBot {
             .json(&payload)
             .send()
             .await
-            .context("Failed to send Telegram request")?;
+            .map_err(reqwest::Error::without_url)
+            .context("Failed to send Telegram getUpdates request")?;
+
+        let result = response
+            .json::<GetUpdatesResponse>()
+            .await
+            .map_err(reqwest::Error::without_url)
+            .context("Failed to parse Telegram getUpdates response")?;
+
+        if !result.ok {
+            anyhow::bail!(
+                "Telegram API error: {}",
+                result.description.unwrap_or_default()
+            );
+        }
+
+        Ok(result.result)
+    }
+}
+
+/// Splits an HTML message into parts that fit Telegram's length limit. Splits happen between
+/// lines where possible; a line that is too long by itself is cut between tags. Tags left open
+/// at a split, including ones spanning several lines, are closed at the end of one part and
+/// reopened at the start of the next. When there is more than one part, each is labelled
+/// "(1/3)" after the header. A header too long to repeat is sent once, as the start of the body.
+fn split_message(header: Option<&str>, body: &str) -> Vec<String> {
+    let header_len = header.map_or(0, |h| utf16_len(h) + 1);
+    let limit = MAX_MESSAGE_LEN
+        .checked_sub(header_len + PART_LABEL_RESERVE)
+        .filter(|&limit| limit >= MIN_BODY_LEN);
+    let Some(limit) = limit else {
+        return split_message(None, &format!("{}\n{}", header.unwrap_or_default(), body));
+    };
 
-        if !response.status().is_success() {
-            let error_text = response
-                .text()
-                .await
-                .unwrap_or_else(|_| "Unknown error".to_string());
-            anyhow::bail!("Telegram API error: {}", error_text);
+    let mut chunks = Vec::new();
+    let mut current = String::new();
+    let mut has_text = false;
+    // Tags opened on earlier lines and not closed yet.
+    let mut open: Vec<OpenTag> = Vec::new();
+    for line in body.lines() {
+        let overhead = utf16_len(&opening_tags(&open)) + closing_len(&open);
+        for piece in split_long_line(line, limit.saturating_sub(overhead)) {
+            if !has_text && piece.trim().is_empty() {
+                // Don't start a part with blank lines.
+                continue;
+            }
+            let mut next_open = open.clone();
+            for token in html_tokens(&piece) {
+                update_open_tags(&mut next_open, token);
+            }
+
+            if has_text
+                && utf16_len(&current) + 1 + utf16_len(&piece) + closing_len(&next_open) > limit
+            {
+                let mut chunk = std::mem::take(&mut current).trim_end().to_string();
+                chunk.push_str(&closing_tags(&open));
+                chunks.push(chunk);
+                current = opening_tags(&open);
+                has_text = false;
+                if piece.trim().is_empty() {
+                    continue;
+                }
+            }
+            if has_text {
+                current.push('\n');
+            }
+            current.push_str(&piece);
+            has_text = true;
+            open = next_open;
+        }
+    }
+    if has_text || chunks.is_empty() {
+        chunks.push(current);
+    }
+
+    let total = chunks.len();
+    chunks
+        .into_iter()
+        .enumerate()
+        .map(|(i, chunk)| {
+            let label = if total > 1 {
+                format!(" ({}/{})", i + 1, total)
+            } else {
+                String::new()
+            };
+            match header {
+                Some(header) => format!("{}{}\n{}", header, label, chunk),
+                None if total > 1 => format!("{}\n{}", label.trim_start(), chunk),
+                None => chunk,
+            }
+        })
+        .collect()
+}
+
+/// Cuts a single line of HTML into pieces no longer than `limit`, never inside a tag or entity.
+fn split_long_line(line: &str, limit: usize) -> Vec<String> {
+    if utf16_len(line) <= limit {
+        return vec![line.to_string()];
+    }
+
+    let mut pieces = Vec::new();
+    let mut open: Vec<OpenTag> = Vec::new();
+    let mut current = String::new();
+    let mut has_text = false;
+
+    for token in html_tokens(line) {
+        let mut next_open = open.clone();
+        update_open_tags(&mut next_open, token);
+
+        if has_text && utf16_len(&current) + utf16_len(token) + closing_len(&next_open) > limit {
+            current.push_str(&closing_tags(&open));
+            pieces.push(std::mem::take(&mut current));
+            current = opening_tags(&open);
+            has_text = false;
+        }
+
+        current.push_str(token);
+        has_text |= !token.starts_with('<');
+        open = next_open;
+    }
+    pieces.push(current);
+
+    pieces
+}
+
+/// A tag that hasn't been closed yet: its name, and the opening tag to repeat it with.
+#[derive(Debug, Clone)]
+struct OpenTag {
+    name: String,
+    tag: String,
+}
+
+/// Tracks the tags still open after `token`.
+fn update_open_tags(open: &mut Vec<OpenTag>, token: &str) {
+    if let Some(name) = token.strip_prefix("</") {
+        let name = name.trim_end_matches('>');
+        if let Some(pos) = open.iter().rposition(|t| t.name == name) {
+            open.truncate(pos);
+        }
+    } else if token.starts_with('<') && !token.ends_with("/>") {
+        let name = token[1..]
+            .split(|c: char| c.is_whitespace() || c == '>')
+            .next()
+            .unwrap_or_default()
+            .to_string();
+        open.push(OpenTag {
+            name,
+            tag: token.to_string(),
+        });
+    }
+}
+
+fn opening_tags(open: &[OpenTag]) -> String {
+    open.iter().map(|t| t.tag.as_str()).collect()
+}
+
+fn closing_tags(open: &[OpenTag]) -> String {
+    open.iter()
+        .rev()
+        .map(|t| format!("</{}>", t.name))
+        .collect()
+}
+
+fn closing_len(open: &[OpenTag]) -> usize {
+    open.iter().map(|t| t.name.len() + 3).sum()
+}
+
+/// Breaks HTML into tags, entities and single characters.
+fn html_tokens(text: &str) -> Vec<&str> {
+    let mut tokens = Vec::new();
+    let mut rest = text;
+    while let Some(c) = rest.chars().next() {
+        let end = match c {
+            '<' => rest.find('>').map_or(1, |i| i + 1),
+            '&' => rest
+                .find(';')
+                .filter(|&i| i <= 10 && !rest[1..i].contains(char::is_whitespace))
+                .map_or(1, |i| i + 1),
+            _ => c.len_utf8(),
+        };
+        tokens.push(&rest[..end]);
+        rest = &rest[end..];
+    }
+    tokens
+}
+
+/// Whether every tag opened in `html` is closed within it, in order.
+fn tags_balanced(html: &str) -> bool {
+    let mut open = Vec::new();
+    for token in html_tokens(html) {
+        if let Some(name) = token.strip_prefix("</") {
+            if open.pop() != Some(name.trim_end_matches('>')) {
+                return false;
+            }
+        } else if token == "<" {
+            return false;
+        } else if token.starts_with('<') && !token.ends_with("/>") {
+            open.push(
+                token[1..]
+                    .split(|c: char| c.is_whitespace() || c == '>')
+                    .next()
+                    .unwrap_or_default(),
+            );
         }
+    }
+    open.is_empty()
+}
+
+fn utf16_len(text: &str) -> usize {
+    text.encode_utf16().count()
+}
+
+/// Delivers trade notifications to one chat through the shared bot.
+pub struct TelegramNotifier {
+    bot: Arc<TelegramBot>,
+    target: ChatTarget,
+    template: Option<Template>,
+}
+
+impl TelegramNotifier {
+    pub fn new(bot: Arc<TelegramBot>, target: ChatTarget, template: Option<Template>) -> Self {
+        Self {
+            bot,
+            target,
+            template,
+        }
+    }
+}
+
+#[async_trait]
+impl Notifier for TelegramNotifier {
+    async fn send(&self, event: &TradeEvent) -> Result<()> {
+        // The first line of a template is the header repeated on every part of a split message,
+        // unless it is too long or leaves a tag open for the lines below.
+        let (header, body) = match self.template.as_ref().and_then(|t| t.render_or_warn(event)) {
+            Some(rendered) => match rendered.split_once('\n') {
+                Some((header, body))
+                    if utf16_len(header) <= MAX_TEMPLATE_HEADER_LEN && tags_balanced(header) =>
+                {
+                    (Some(header.to_string()), body.to_string())
+                }
+                _ => (None, rendered),
+            },
+            None => {
+                let message = event.to_message();
+                (Some(message.header.to_html()), message.body_html())
+            }
+        };
+
+        // High priority messages are marked at the top of every part. Redirect alerts always
+        // are, so that a silent destination can't hide them.
+        let priority = match event.kind {
+            TradeEventKind::SuspectedRedirect => Priority::High,
+            _ => event.priority,
+        };
+        let header = match (header, priority) {
+            (Some(header), Priority::High) => Some(format!("{} {}", HIGH_PRIORITY_MARKER, header)),
+            (None, Priority::High) => Some(HIGH_PRIORITY_MARKER.to_string()),
+            (header, Priority::Normal) => header,
+        };
+        self.bot
+            .send_to(&self.target, header.as_deref(), &body, priority)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::TradeOfferState;
+
+    #[test]
+    fn test_split_short_message_is_untouched() {
+        assert_eq!(
+            split_message(Some("<b>Account: Bot1</b>"), "Trade ID: 1\n- Key"),
+            vec!["<b>Account: Bot1</b>\nTrade ID: 1\n- Key"]
+        );
+    }
+
+    #[test]
+    fn test_split_long_message_on_lines() {
+        let body = (0..600)
+            .map(|i| format!("- {}x <i>Item number {}</i>", i, i))
+            .collect::<Vec<_>>()
+            .join("\n");
+        let parts = split_message(Some("<b>Account: Bot1</b>"), &body);
+
+        assert!(parts.len() > 1);
+        for (i, part) in parts.iter().enumerate() {
+            assert!(utf16_len(part) <= MAX_MESSAGE_LEN);
+            assert!(part.starts_with(&format!(
+                "<b>Account: Bot1</b> ({}/{})\n- ",
+                i + 1,
+                parts.len()
+            )));
+        }
+        let rejoined: Vec<&str> = parts.iter().flat_map(|p| p.lines().skip(1)).collect();
+        assert_eq!(rejoined.join("\n"), body);
+    }
+
+    #[test]
+    fn test_split_overlong_header_goes_in_body() {
+        let header = format!("<b>{}</b>", "Account ".repeat(600));
+        let parts = split_message(Some(&header), "Trade ID: 1\n- Key");
+
+        assert!(parts.len() > 1);
+        for part in &parts {
+            assert!(utf16_len(part) <= MAX_MESSAGE_LEN);
+        }
+        assert!(parts[0].starts_with("(1/2)\n<b>Account "));
+        assert!(parts.last().unwrap().ends_with("Trade ID: 1\n- Key"));
+    }
+
+    #[test]
+    fn test_split_keeps_multiline_tags_balanced() {
+        let quote = (0..400)
+            .map(|i| format!("Item number {}", i))
+            .collect::<Vec<_>>()
+            .join("\n");
+        let body = format!(
+            "Trade ID: 1\n<blockquote><b>Items:</b>\n{}</blockquote>",
+            quote
+        );
+        let parts = split_message(Some("<b>Account: Bot1</b>"), &body);
+
+        assert!(parts.len() > 1);
+        for part in &parts {
+            assert!(utf16_len(part) <= MAX_MESSAGE_LEN);
+            assert!(tags_balanced(part), "unbalanced part: {}", part);
+        }
+        assert!(parts[1].contains("\n<blockquote>Item number"));
+        assert!(parts[0].ends_with("</blockquote>"));
+    }
+
+    #[test]
+    fn test_split_overlong_line_keeps_tags_balanced() {
+        let line = format!("<b>{}</b>", "Tom &amp; Jerry ".repeat(400));
+        let pieces = split_long_line(&line, 1000);
+
+        assert!(pieces.len() > 1);
+        for piece in &pieces {
+            assert!(utf16_len(piece) <= 1000);
+            assert!(piece.starts_with("<b>"));
+            assert!(piece.ends_with("</b>"));
+            assert_eq!(piece.matches("<b>").count(), piece.matches("</b>").count());
+            // Entities are never cut in half.
+            assert_eq!(piece.matches('&').count(), piece.matches("&amp;").count());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_high_priority_is_marked_and_loud() {
+        let path =
+            std::env::temp_dir().join(format!("test_telegram_outbox_{}.json", std::process::id()));
+        let bot = Arc::new(TelegramBot::new(
+            "123:abc".to_string(),
+            "-999".to_string(),
+            Outbox::new(&path).unwrap(),
+        ));
+        let target = ChatTarget {
+            chat_id: "-999".to_string(),
+            thread_id: None,
+            silent: true,
+        };
+        let notifier = TelegramNotifier::new(bot.clone(), target, None);
+
+        let mut event = TradeEvent::new(
+            "Bot1",
+            TradeEventKind::Completed,
+            "1",
+            TradeOfferState::Accepted,
+        );
+        event.priority = Priority::High;
+        notifier.send(&event).await.unwrap();
+
+        let message = bot.outbox.front().unwrap();
+        assert!(message.high_priority);
+        assert!(message.text.starts_with("⚠️ <b>"));
+
+        // Redirect alerts are loud whatever their priority.
+        bot.outbox.pop_front().unwrap();
+        let redirect = TradeEvent::new(
+            "Bot1",
+            TradeEventKind::SuspectedRedirect,
+            "2",
+            TradeOfferState::Active,
+        );
+        notifier.send(&redirect).await.unwrap();
+        assert!(bot.outbox.front().unwrap().high_priority);
+
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_tags_balanced() {
+        assert!(tags_balanced("<b>Account:</b> <a href=\"x\">Bot1</a>"));
+        assert!(tags_balanced("Plain &amp; simple"));
+        assert!(!tags_balanced("<b>Account: Bot1"));
+        assert!(!tags_balanced("<b><i>Bot1</b></i>"));
+        assert!(!tags_balanced("Bot1</b>"));
+    }
+
+    #[test]
+    fn test_classify_error() {
+        let body = r#"{"ok":false,"error_code":429,"description":"Too Many Requests: retry after 17","parameters":{"retry_after":17}}"#;
+        assert!(matches!(
+            classify_error(StatusCode::TOO_MANY_REQUESTS, body),
+            SendError::RetryAfter(d) if d == Duration::from_secs(17)
+        ));
+        assert!(matches!(
+            classify_error(StatusCode::BAD_GATEWAY, "Bad Gateway"),
+            SendError::Transient(_)
+        ));
+        assert!(matches!(
+            classify_error(
+                StatusCode::BAD_REQUEST,
+                r#"{"ok":false,"error_code":400,"description":"Bad Request: chat not found"}"#
+            ),
+            SendError::Permanent(e) if e.to_string().contains("chat not found")
+        ));
+    }
+
+    #[test]
+    fn test_update_deserialization() {
+        let body = r#"{
+            "ok": true,
+            "result": [
+                {"update_id": 10, "message": {"message_id": 1, "chat": {"id": -999, "type": "group"}, "text": "/status"}},
+                {"update_id": 11, "edited_message": {}}
+            ]
+        }"#;
+
+        let response: GetUpdatesResponse = serde_json::from_str(body).unwrap();
 
-        Ok(())
+        assert_eq!(response.result.len(), 2);
+        let message = response.result[0].message.as_ref().unwrap();
+        assert_eq!(message.chat.id, -999);
+        assert_eq!(message.text.as_deref(), Some("/status"));
+        assert!(response.result[1].message.is_none());
     }
 }
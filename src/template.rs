@@ -0,0 +1,177 @@
+use anyhow::{Context, Result};
+use log::warn;
+use minijinja::{Environment, Value, context};
+use std::{fs, path::Path};
+
+use crate::{
+    message::{escape_html, escape_markdown},
+    notifier::{Priority, TradeEvent},
+};
+
+const TEMPLATE_NAME: &str = "notification";
+
+/// The markup a template produces. Interpolated values are escaped for it unless marked `|safe`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Markup {
+    /// Telegram's `parse_mode: HTML`.
+    Html,
+    /// Discord markdown.
+    Markdown,
+}
+
+impl Markup {
+    fn escape(self, text: &str) -> String {
+        match self {
+            Self::Html => escape_html(text),
+            Self::Markdown => escape_markdown(text),
+        }
+    }
+}
+
+/// A user-supplied notification layout, written in Jinja syntax.
+pub struct Template {
+    env: Environment<'static>,
+}
+
+impl Template {
+    /// Loads and compiles a template file, so that syntax errors are reported at startup.
+    pub fn load(path: &Path, markup: Markup) -> Result<Self> {
+        let source = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read template file: {:?}", path))?;
+        Self::from_source(source, markup)
+            .with_context(|| format!("Failed to parse template file: {:?}", path))
+    }
+
+    fn from_source(source: String, markup: Markup) -> Result<Self> {
+        let mut env = Environment::new();
+        env.set_trim_blocks(true);
+        env.set_lstrip_blocks(true);
+        env.set_formatter(move |out, _state, value| {
+            if value.is_none() || value.is_undefined() {
+                return Ok(());
+            }
+            let text = value.to_string();
+            if value.is_safe() {
+                out.write_str(&text)?;
+            } else {
+                out.write_str(&markup.escape(&text))?;
+            }
+            Ok(())
+        });
+        env.add_template_owned(TEMPLATE_NAME, source)?;
+
+        Ok(Self { env })
+    }
+
+    pub fn render(&self, event: &TradeEvent) -> Result<String> {
+        let template = self.env.get_template(TEMPLATE_NAME)?;
+        let rendered = template
+            .render(event_context(event))
+            .context("Failed to render notification template")?;
+        Ok(rendered.trim().to_string())
+    }
+
+    /// Renders the event, logging failures so the caller can fall back to the built-in layout.
+    pub fn render_or_warn(&self, event: &TradeEvent) -> Option<String> {
+        match self.render(event) {
+            Ok(rendered) => Some(rendered),
+            Err(e) => {
+                warn!(
+                    "[{}] {:#}; using the built-in layout instead.",
+                    event.account, e
+                );
+                None
+            }
+        }
+    }
+}
+
+/// The variables available to templates.
+fn event_context(event: &TradeEvent) -> Value {
+    let (id_label, received_label, given_label) = event.labels();
+
+    let partner = event.partner.as_ref().map(|p| {
+        context! {
+            steamid => p.steamid,
+            name => p.display_name(),
+            profile_url => p.profile_url(),
+            avatar_url => p.avatar_url,
+        }
+    });
+    let valuation = event.valuation.as_ref().map(|v| {
+        context! {
+            items => v.item_lines(),
+            summary => v.summary_line(),
+            total_received => v.format(v.total_received() as i64),
+            total_given => v.format(v.total_given() as i64),
+            net => v.format(v.net()),
+        }
+    });
+
+    context! {
+        account => event.account,
+        kind => event.kind.name(),
+        trade_id => event.trade_id,
+        id_label,
+        received_label,
+        given_label,
+        state => event.state.name(),
+        previous_state => event.previous_state.map(|s| s.name()),
+        state_line => event.state_line(),
+        alert => event.alert_line(),
+        high_priority => event.priority == Priority::High,
+        partner,
+        message => event.message,
+        items_received => event.items_received,
+        items_given => event.items_given,
+        valuation,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{models::TradeOfferState, notifier::TradeEventKind, profiles::Partner};
+
+    fn event() -> TradeEvent {
+        let mut event = TradeEvent::new(
+            "Bot1",
+            TradeEventKind::Completed,
+            "123",
+            TradeOfferState::Accepted,
+        );
+        let mut partner = Partner::new("76561197960287930".to_string());
+        partner.name = Some("<Gabe>".to_string());
+        event.partner = Some(partner);
+        event.items_received = vec!["2x Key".to_string(), "Tom & Jerry".to_string()];
+        event
+    }
+
+    #[test]
+    fn test_render_template() {
+        let source = r#"<b>{{ account }}</b> traded with {{ partner.name }} ({{ kind }})
+{% for item in items_received %}
++ {{ item }}
+{% endfor %}
+{% if items_given %}given something{% endif %}
+{{ "<i>done</i>"|safe }}"#;
+
+        let html = Template::from_source(source.to_string(), Markup::Html).unwrap();
+        assert_eq!(
+            html.render(&event()).unwrap(),
+            "<b>Bot1</b> traded with &lt;Gabe&gt; (completed)\n+ 2x Key\n+ Tom &amp; Jerry\n<i>done</i>"
+        );
+
+        let markdown = Template::from_source(
+            "**{{ account }}** {{ partner.name }} {{ message }}".to_string(),
+            Markup::Markdown,
+        )
+        .unwrap();
+        assert_eq!(markdown.render(&event()).unwrap(), "**Bot1** \\<Gabe\\>");
+    }
+
+    #[test]
+    fn test_invalid_template_is_rejected() {
+        assert!(Template::from_source("{% for %}".to_string(), Markup::Html).is_err());
+    }
+}
@@ -0,0 +1,332 @@
+use anyhow::Result;
+use log::info;
+use regex::Regex;
+use std::collections::HashMap;
+
+use crate::{
+    config::{Config, NotifierConfig},
+    ratelimit::RateLimiter,
+    steam::SteamClient,
+    telegram::TelegramBot,
+};
+
+/// Problems found in a config, each with the path of the field it concerns.
+#[derive(Debug, Default)]
+struct Problems(Vec<String>);
+
+impl Problems {
+    fn push(&mut self, field: impl std::fmt::Display, problem: impl std::fmt::Display) {
+        self.0.push(format!("{}: {}", field, problem));
+    }
+
+    /// Fails with every problem listed, one per line.
+    fn into_result(self, what: &str) -> Result<()> {
+        if self.0.is_empty() {
+            return Ok(());
+        }
+        anyhow::bail!(
+            "{} {} found:\n  {}",
+            self.0.len(),
+            what,
+            self.0.join("\n  ")
+        );
+    }
+}
+
+/// Checks the values in a parsed config, reporting every problem at once.
+pub fn validate(config: &Config) -> Result<()> {
+    let mut problems = Problems::default();
+
+    if config.polling_interval_seconds == 0 {
+        problems.push("polling_interval_seconds", "must be at least 1");
+    }
+    if config.item_cache.max_entries == 0 {
+        problems.push("item_cache.max_entries", "must be at least 1");
+    }
+    if config.steam_rate_limit.requests_per_minute == 0 {
+        problems.push("steam_rate_limit.requests_per_minute", "must be at least 1");
+    }
+    if config.steam_rate_limit.burst == 0 {
+        problems.push("steam_rate_limit.burst", "must be at least 1");
+    }
+
+    if !config.telegram_token.is_empty() && !is_bot_token(&config.telegram_token) {
+        problems.push(
+            "telegram_token",
+            "expected a bot token like 123456789:AAE..., as given by @BotFather",
+        );
+    }
+    if !config.telegram_chat_id.is_empty() && !is_chat_id(&config.telegram_chat_id) {
+        problems.push(
+            "telegram_chat_id",
+            "expected a numeric chat ID like -1001234567890, or a channel like @name",
+        );
+    }
+    if config.telegram_commands
+        && (config.telegram_token.is_empty() || config.telegram_chat_id.is_empty())
+    {
+        problems.push(
+            "telegram_commands",
+            "needs telegram_token and telegram_chat_id",
+        );
+    } else if config.telegram_commands && config.telegram_chat_id.starts_with('@') {
+        // Updates only carry the numeric ID of the chat they came from.
+        problems.push(
+            "telegram_commands",
+            "needs a numeric telegram_chat_id to recognize commands, not a @name",
+        );
+    }
+
+    if config.accounts.is_empty() {
+        problems.push("accounts", "no accounts to watch");
+    }
+    // Account names are matched case-insensitively by commands and --account.
+    let mut names: HashMap<String, usize> = HashMap::new();
+    for (i, account) in config.accounts.iter().enumerate() {
+        let name = account.name.trim();
+        if name.is_empty() {
+            problems.push(format!("accounts[{}].name", i), "must not be empty");
+        } else if let Some(first) = names.get(&name.to_lowercase()) {
+            problems.push(
+                format!("accounts[{}].name", i),
+                format!("{:?} is already used by accounts[{}]", name, first),
+            );
+        } else {
+            names.insert(name.to_lowercase(), i);
+        }
+
+        if !is_api_key(&account.api_key) {
+            problems.push(
+                format!("accounts[{}].api_key", i),
+                "expected a 32 character Steam Web API key",
+            );
+        }
+
+        for (j, notifier) in account.notifiers.iter().enumerate() {
+            check_notifier(
+                &mut problems,
+                config,
+                &format!("accounts[{}].notifiers[{}]", i, j),
+                notifier,
+            );
+        }
+    }
+
+    // The global notifier is only used by accounts without their own.
+    if config.accounts.iter().any(|a| a.notifiers.is_empty()) {
+        check_notifier(&mut problems, config, "notifier", &config.notifier);
+    }
+
+    for (i, rule) in config.rules.iter().enumerate() {
+        for (field, pattern) in [("name", &rule.name), ("type", &rule.item_type)] {
+            if let Some(Err(e)) = pattern.as_deref().map(Regex::new) {
+                problems.push(
+                    format!("rules[{}].{}", i, field),
+                    format!("invalid regex: {}", e),
+                );
+            }
+        }
+        if let (Some(min), Some(max)) = (rule.min_items, rule.max_items)
+            && min > max
+        {
+            problems.push(format!("rules[{}]", i), "min_items is above max_items");
+        }
+        if let (Some(min), Some(max)) = (rule.min_value, rule.max_value)
+            && min > max
+        {
+            problems.push(format!("rules[{}]", i), "min_value is above max_value");
+        }
+    }
+
+    problems.into_result("config problems")
+}
+
+fn check_notifier(
+    problems: &mut Problems,
+    config: &Config,
+    field: &str,
+    notifier: &NotifierConfig,
+) {
+    match notifier {
+        NotifierConfig::Telegram { chat_id, .. } => {
+            if config.telegram_token.is_empty() {
+                problems.push(field, "Telegram destination needs telegram_token");
+            }
+            match chat_id {
+                Some(chat_id) if !is_chat_id(chat_id) => problems.push(
+                    format!("{}.chat_id", field),
+                    "expected a numeric chat ID like -1001234567890, or a channel like @name",
+                ),
+                None if config.telegram_chat_id.is_empty() => problems.push(
+                    field,
+                    "Telegram destination needs a chat_id, or telegram_chat_id",
+                ),
+                _ => {}
+            }
+        }
+        NotifierConfig::Discord { webhook_url, .. } => {
+            if !webhook_url.starts_with("https://") {
+                problems.push(
+                    format!("{}.webhook_url", field),
+                    "expected a Discord webhook URL starting with https://",
+                );
+            }
+        }
+    }
+}
+
+/// Checks every account's Steam API key and the Telegram token against the live APIs,
+/// reporting every failure at once.
+pub async fn probe(config: &Config, telegram: Option<&TelegramBot>) -> Result<()> {
+    let mut problems = Problems::default();
+    let limiter = RateLimiter::new(&config.steam_rate_limit);
+
+    for (i, account) in config.accounts.iter().enumerate() {
+        match SteamClient::new(account.api_key.clone(), limiter.clone())
+            .check_api_key()
+            .await
+        {
+            Ok(()) => info!("[{}] Steam API key accepted.", account.name),
+            Err(e) => problems.push(format!("accounts[{}].api_key", i), format!("{:#}", e)),
+        }
+    }
+
+    if let Some(bot) = telegram {
+        match bot.get_me().await {
+            Ok(username) => info!("Telegram token accepted for @{}.", username),
+            Err(e) => problems.push("telegram_token", format!("{:#}", e)),
+        }
+    }
+
+    problems.into_result("credentials failed the live check")
+}
+
+fn is_api_key(key: &str) -> bool {
+    key.len() == 32 && key.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+/// `<bot id>:<secret>`, as issued by @BotFather.
+fn is_bot_token(token: &str) -> bool {
+    token.split_once(':').is_some_and(|(id, secret)| {
+        !id.is_empty()
+            && id.chars().all(|c| c.is_ascii_digit())
+            && !secret.is_empty()
+            && secret
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+    })
+}
+
+/// A numeric chat ID, negative for groups and channels, or a public channel's `@username`.
+fn is_chat_id(chat_id: &str) -> bool {
+    if let Some(username) = chat_id.strip_prefix('@') {
+        return !username.is_empty()
+            && username
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_');
+    }
+    chat_id.parse::<i64>().is_ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY: &str = "0123456789ABCDEF0123456789ABCDEF";
+
+    fn config(json: &str) -> Config {
+        serde_json::from_str(json).expect("Failed to parse Config")
+    }
+
+    #[test]
+    fn test_valid_config() {
+        let config = config(&format!(
+            r#"{{
+                "telegram_token": "123456:ABC-def_1",
+                "telegram_chat_id": -1001234567890,
+                "polling_interval_seconds": 30,
+                "accounts": [
+                    {{ "name": "Main", "api_key": "{KEY}" }},
+                    {{
+                        "name": "Storage",
+                        "api_key": "{KEY}",
+                        "notifiers": [{{ "type": "telegram", "chat_id": "@tradelog" }}]
+                    }}
+                ]
+            }}"#
+        ));
+        assert!(validate(&config).is_ok());
+    }
+
+    #[test]
+    fn test_reports_every_problem() {
+        let config = config(&format!(
+            r#"{{
+                "telegram_token": "not a token",
+                "telegram_chat_id": "chat",
+                "polling_interval_seconds": 0,
+                "accounts": [
+                    {{ "name": "Main", "api_key": "{KEY}" }},
+                    {{ "name": "main", "api_key": "KEY2" }},
+                    {{
+                        "name": " ",
+                        "api_key": "{KEY}",
+                        "notifiers": [{{ "type": "discord", "webhook_url": "discord.com/api/webhooks/1/abc" }}]
+                    }}
+                ],
+                "rules": [{{ "name": "(", "min_items": 5, "max_items": 1, "action": "exclude" }}]
+            }}"#
+        ));
+
+        let error = validate(&config).unwrap_err().to_string();
+        for expected in [
+            "9 config problems found",
+            "polling_interval_seconds:",
+            "telegram_token:",
+            "telegram_chat_id:",
+            "accounts[1].name: \"main\" is already used by accounts[0]",
+            "accounts[1].api_key:",
+            "accounts[2].name: must not be empty",
+            "accounts[2].notifiers[0].webhook_url:",
+            "rules[0].name: invalid regex",
+            "rules[0]: min_items is above max_items",
+        ] {
+            assert!(
+                error.contains(expected),
+                "missing {:?} in:\n{}",
+                expected,
+                error
+            );
+        }
+    }
+
+    #[test]
+    fn test_telegram_destination_needs_bot_and_chat() {
+        let config = config(&format!(
+            r#"{{
+                "polling_interval_seconds": 30,
+                "accounts": [{{ "name": "Main", "api_key": "{KEY}" }}]
+            }}"#
+        ));
+
+        let error = validate(&config).unwrap_err().to_string();
+        assert!(error.contains("notifier: Telegram destination needs telegram_token"));
+        assert!(error.contains("notifier: Telegram destination needs a chat_id"));
+    }
+
+    #[test]
+    fn test_commands_need_numeric_chat_id() {
+        let config = config(&format!(
+            r#"{{
+                "telegram_token": "123456:ABC-def_1",
+                "telegram_chat_id": "@tradelog",
+                "telegram_commands": true,
+                "polling_interval_seconds": 30,
+                "accounts": [{{ "name": "Main", "api_key": "{KEY}" }}]
+            }}"#
+        ));
+
+        let error = validate(&config).unwrap_err().to_string();
+        assert!(error.contains("telegram_commands: needs a numeric telegram_chat_id"));
+    }
+}